@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+pub const MAX_BACKOFF: Duration = Duration::from_secs(16);
+
+// Delays between retries that double each time up to MAX_BACKOFF, so a
+// struggling service gets longer to recover without a long outage being
+// waited out with ever longer sleeps.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    initial: Duration,
+    delay: Duration,
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Self::starting_at(INITIAL_BACKOFF)
+    }
+
+    pub fn starting_at(initial: Duration) -> Self {
+        Self {
+            initial,
+            delay: initial,
+        }
+    }
+
+    // The delay before the next retry, without using it up.
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    // The delay before the next retry, doubling the one after.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.delay;
+        self.delay = (delay * 2).min(MAX_BACKOFF);
+        delay
+    }
+
+    pub async fn wait(&mut self) {
+        tokio::time::sleep(self.next_delay()).await;
+    }
+
+    // Start again from the initial delay after something succeeds.
+    pub fn reset(&mut self) {
+        self.delay = self.initial;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+
+use aws_smithy_http::result::SdkError;
+use aws_smithy_types::error::metadata::ProvideErrorMetadata;
+use serde::Serialize;
+
+const ACCESS_DENIED: &[&str] = &["AccessDenied", "AllAccessDisabled", "AccessDeniedException"];
+
+// The bucket, object or upload asked about doesn't exist.  HEAD requests have
+// no body, so all they can say is NotFound.
+const NOT_FOUND: &[&str] = &[
+    "NoSuchBucket",
+    "NoSuchKey",
+    "NoSuchUpload",
+    "NoSuchVersion",
+    "NotFound",
+];
+
+// The bucket exists but simply doesn't have the setting asked about.
+const NOT_CONFIGURED: &[&str] = &[
+    "NoSuchTagSet",
+    "NoSuchPublicAccessBlockConfiguration",
+    "NoSuchBucketPolicy",
+    "NoSuchLifecycleConfiguration",
+    "NoSuchCORSConfiguration",
+    "NoSuchWebsiteConfiguration",
+    "ServerSideEncryptionConfigurationNotFoundError",
+    "OwnershipControlsNotFoundError",
+];
+
+// S3 redirects requests for a bucket to the region it's in, which the SDK
+// doesn't follow.
+const WRONG_REGION: &[&str] = &[
+    "PermanentRedirect",
+    "AuthorizationHeaderMalformed",
+    "IllegalLocationConstraintException",
+];
+
+const TRANSIENT: &[&str] = &[
+    "SlowDown",
+    "Throttling",
+    "ThrottlingException",
+    "TooManyRequestsException",
+    "RequestLimitExceeded",
+    "RequestTimeout",
+    "InternalError",
+    "ServiceUnavailable",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    AccessDenied,
+    NotFound,
+    NotConfigured,
+    WrongRegion,
+    Transient,
+    Other,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::AccessDenied => write!(f, "access denied"),
+            ErrorKind::NotFound => write!(f, "does not exist"),
+            ErrorKind::NotConfigured => write!(f, "not configured"),
+            ErrorKind::WrongRegion => write!(f, "bucket is in another region"),
+            ErrorKind::Transient => write!(f, "transient error, try again later"),
+            ErrorKind::Other => write!(f, "error"),
+        }
+    }
+}
+
+pub fn classify<E: ProvideErrorMetadata, R>(err: &SdkError<E, R>) -> ErrorKind {
+    match err {
+        // The request never got an answer.
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) => ErrorKind::Transient,
+        _ => match err.code() {
+            Some(code) if ACCESS_DENIED.contains(&code) => ErrorKind::AccessDenied,
+            Some(code) if NOT_FOUND.contains(&code) => ErrorKind::NotFound,
+            Some(code) if NOT_CONFIGURED.contains(&code) => ErrorKind::NotConfigured,
+            Some(code) if WRONG_REGION.contains(&code) => ErrorKind::WrongRegion,
+            Some(code) if TRANSIENT.contains(&code) => ErrorKind::Transient,
+            _ => ErrorKind::Other,
+        },
+    }
+}
+
+// Ok(None) rather than an error of the given kind, e.g. for a setting that
+// isn't there at all.
+pub fn none_if<T, E: ProvideErrorMetadata, R>(
+    result: Result<T, SdkError<E, R>>,
+    kind: ErrorKind,
+) -> Result<Option<T>, SdkError<E, R>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if classify(&err) == kind => Ok(None),
+        Err(err) => Err(err),
+    }
+}
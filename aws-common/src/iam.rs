@@ -0,0 +1,48 @@
+use std::time::{Duration, Instant};
+
+use aws_sdk_iam::error::ProvideErrorMetadata;
+use aws_sdk_iam::Client;
+
+use crate::Backoff;
+
+// IAM is eventually consistent, so poll until the Instance Profile can be read
+// back with its Role before anyone tries to use it.  This is necessary but not
+// sufficient for EC2, which can take a little longer to see it.
+pub async fn wait_for_instance_profile(
+    client: &Client,
+    profile_name: &str,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let deadline = Instant::now() + timeout;
+    let mut backoff = Backoff::new();
+    loop {
+        let ready = match client
+            .get_instance_profile()
+            .instance_profile_name(profile_name)
+            .send()
+            .await
+        {
+            Ok(resp) => resp
+                .instance_profile()
+                .and_then(|p| p.roles())
+                .is_some_and(|roles| !roles.is_empty()),
+            Err(err) if err.code() == Some("NoSuchEntity") => false,
+            Err(err) => return Err(err.into()),
+        };
+
+        if ready {
+            println!("Instance Profile {} is ready", profile_name);
+            return Ok(());
+        }
+        if Instant::now() + backoff.delay() > deadline {
+            anyhow::bail!(
+                "Instance Profile {} was not ready after {} seconds",
+                profile_name,
+                timeout.as_secs()
+            );
+        }
+
+        println!("Waiting for Instance Profile {}", profile_name);
+        backoff.wait().await;
+    }
+}
@@ -0,0 +1,9 @@
+pub mod backoff;
+pub mod errors;
+#[cfg(feature = "iam")]
+pub mod iam;
+#[cfg(feature = "s3")]
+pub mod s3;
+
+pub use backoff::Backoff;
+pub use errors::{classify, none_if, ErrorKind};
@@ -0,0 +1,89 @@
+use aws_sdk_s3::config::Region;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::operation::create_bucket::CreateBucketError;
+use aws_sdk_s3::operation::get_bucket_location::GetBucketLocationError;
+use aws_sdk_s3::types::{BucketLocationConstraint, CreateBucketConfiguration};
+use aws_sdk_s3::Client;
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+
+use crate::errors::{classify, none_if, ErrorKind};
+
+// The region the client sends requests to.
+pub fn client_region(client: &Client) -> String {
+    client
+        .conf()
+        .region()
+        .map(|r| r.to_string())
+        .unwrap_or_else(|| "us-east-1".to_string())
+}
+
+// The region a bucket is in.  The error is left as it is so that callers can
+// retry it or treat NoSuchBucket as they need to.
+pub async fn bucket_region(
+    client: &Client,
+    bucket: &str,
+) -> Result<String, SdkError<GetBucketLocationError, HttpResponse>> {
+    let resp = client.get_bucket_location().bucket(bucket).send().await?;
+
+    // Buckets in us-east-1 have no location constraint, and some very old
+    // buckets in eu-west-1 have the constraint EU.
+    let region = match resp.location_constraint().map(|c| c.as_str()) {
+        None | Some("") => "us-east-1",
+        Some("EU") => "eu-west-1",
+        Some(region) => region,
+    };
+    Ok(region.to_string())
+}
+
+// A client for the region a bucket is in, requests for a bucket sent to any
+// other region are redirected and fail.
+pub async fn client_for_bucket(client: &Client, bucket: &str) -> anyhow::Result<Client> {
+    match none_if(bucket_region(client, bucket).await, ErrorKind::NotFound)? {
+        Some(region) => Ok(regional_client(client, &region)),
+        None => anyhow::bail!("bucket {} does not exist", bucket),
+    }
+}
+
+pub fn regional_client(client: &Client, region: &str) -> Client {
+    if client_region(client) == region {
+        return client.clone();
+    }
+
+    let config = client
+        .conf()
+        .to_builder()
+        .region(Region::new(region.to_string()))
+        .build();
+    Client::from_conf(config)
+}
+
+// Create a bucket in the client's region and return the region.  us-east-1 is
+// the default and rejects a location constraint naming it.
+pub async fn create_bucket(
+    client: &Client,
+    bucket: &str,
+) -> Result<String, SdkError<CreateBucketError, HttpResponse>> {
+    let region = client_region(client);
+    let cfg = (region != "us-east-1").then(|| {
+        CreateBucketConfiguration::builder()
+            .location_constraint(BucketLocationConstraint::from(region.as_str()))
+            .build()
+    });
+
+    client
+        .create_bucket()
+        .bucket(bucket)
+        .set_create_bucket_configuration(cfg)
+        .send()
+        .await?;
+    Ok(region)
+}
+
+// Worth trying again: throttling, timeouts, and any 5xx whatever its code.
+// Anything else will fail the same way however many times it's sent.
+pub fn is_retryable<E: ProvideErrorMetadata>(err: &SdkError<E, HttpResponse>) -> bool {
+    classify(err) == ErrorKind::Transient
+        || err
+            .raw_response()
+            .is_some_and(|resp| resp.status().is_server_error())
+}
@@ -0,0 +1,148 @@
+use std::io::{self, Write};
+
+use aws_sdk_s3::operation::list_object_versions::ListObjectVersionsOutput;
+use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+use aws_sdk_s3::Client;
+use log::info;
+
+// The most keys delete_objects accepts, and the most list_object_versions
+// returns, in one request.
+const BATCH: usize = 1000;
+
+#[derive(Debug, Default)]
+pub struct Contents {
+    pub versions: u64,
+    pub delete_markers: u64,
+    pub bytes: u64,
+}
+
+// Every version of every object and every delete marker in the bucket.  A
+// bucket that has never had versioning on has one version of each object,
+// with the version ID "null".
+fn identifiers(page: &ListObjectVersionsOutput) -> Vec<ObjectIdentifier> {
+    let versions = page
+        .versions()
+        .unwrap_or_default()
+        .iter()
+        .map(|v| (v.key(), v.version_id()));
+    let delete_markers = page
+        .delete_markers()
+        .unwrap_or_default()
+        .iter()
+        .map(|m| (m.key(), m.version_id()));
+
+    versions
+        .chain(delete_markers)
+        .map(|(key, version_id)| {
+            ObjectIdentifier::builder()
+                .set_key(key.map(str::to_string))
+                .set_version_id(version_id.map(str::to_string))
+                .build()
+        })
+        .collect()
+}
+
+async fn list_page(
+    client: &Client,
+    bucket: &str,
+    key_marker: Option<String>,
+    version_id_marker: Option<String>,
+) -> anyhow::Result<ListObjectVersionsOutput> {
+    let page = client
+        .list_object_versions()
+        .bucket(bucket)
+        .max_keys(BATCH as i32)
+        .set_key_marker(key_marker)
+        .set_version_id_marker(version_id_marker)
+        .send()
+        .await?;
+    Ok(page)
+}
+
+pub async fn count(client: &Client, bucket: &str) -> anyhow::Result<Contents> {
+    let mut contents = Contents::default();
+    let (mut key_marker, mut version_id_marker) = (None, None);
+    loop {
+        let page = list_page(client, bucket, key_marker, version_id_marker).await?;
+
+        for version in page.versions().unwrap_or_default() {
+            contents.versions += 1;
+            contents.bytes += version.size() as u64;
+        }
+        contents.delete_markers += page.delete_markers().unwrap_or_default().len() as u64;
+
+        if !page.is_truncated() {
+            break;
+        }
+        key_marker = page.next_key_marker().map(str::to_string);
+        version_id_marker = page.next_version_id_marker().map(str::to_string);
+    }
+    Ok(contents)
+}
+
+// Delete a page at a time rather than listing everything first, so a bucket
+// with millions of versions doesn't have to fit in memory.  Returns the
+// number of versions and delete markers removed.
+pub async fn empty_bucket(client: &Client, bucket: &str) -> anyhow::Result<u64> {
+    let mut deleted = 0;
+    let (mut key_marker, mut version_id_marker) = (None, None);
+    loop {
+        let page = list_page(client, bucket, key_marker, version_id_marker).await?;
+
+        for batch in identifiers(&page).chunks(BATCH) {
+            let resp = client
+                .delete_objects()
+                .bucket(bucket)
+                .delete(
+                    Delete::builder()
+                        .set_objects(Some(batch.to_vec()))
+                        .quiet(true)
+                        .build(),
+                )
+                .send()
+                .await?;
+
+            // Object Lock and MFA delete refuse individual deletes without
+            // failing the request.
+            let errors = resp.errors().unwrap_or_default();
+            for error in errors {
+                println!(
+                    "Failed to delete {} version {}: {}",
+                    error.key().unwrap_or_default(),
+                    error.version_id().unwrap_or_default(),
+                    error.message().unwrap_or_default()
+                );
+            }
+            if !errors.is_empty() {
+                anyhow::bail!("{} versions could not be deleted", errors.len());
+            }
+
+            deleted += batch.len() as u64;
+            info!(
+                "Deleted {} versions and delete markers from {}",
+                deleted, bucket
+            );
+        }
+
+        if !page.is_truncated() {
+            break;
+        }
+        key_marker = page.next_key_marker().map(str::to_string);
+        version_id_marker = page.next_version_id_marker().map(str::to_string);
+    }
+    Ok(deleted)
+}
+
+// Ask for the bucket name to be typed back before anything is deleted.
+pub fn confirm(bucket: &str, contents: &Contents) -> anyhow::Result<bool> {
+    println!(
+        "This will permanently delete {} object versions ({} bytes) and {} delete markers from {}, then delete the bucket.",
+        contents.versions, contents.bytes, contents.delete_markers, bucket
+    );
+    print!("Type the name of the bucket to continue: ");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim() == bucket)
+}
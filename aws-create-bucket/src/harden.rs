@@ -0,0 +1,326 @@
+use std::fmt;
+
+use aws_common::{none_if, ErrorKind};
+use aws_sdk_s3::types::{
+    BucketLoggingStatus, BucketVersioningStatus, LoggingEnabled, ObjectOwnership,
+    OwnershipControls, OwnershipControlsRule, PublicAccessBlockConfiguration, ServerSideEncryption,
+    ServerSideEncryptionByDefault, ServerSideEncryptionConfiguration, ServerSideEncryptionRule,
+    VersioningConfiguration,
+};
+use aws_sdk_s3::Client;
+use log::info;
+use serde_json::{json, Value};
+
+pub struct Hardening {
+    // Encrypt with this KMS key rather than S3 managed keys.
+    pub kms_key_id: Option<String>,
+    // Bucket to write access logs to, it must be in the same region and allow
+    // logging.s3.amazonaws.com to put objects.
+    pub log_bucket: Option<String>,
+    pub log_prefix: String,
+}
+
+impl Hardening {
+    fn sse_algorithm(&self) -> ServerSideEncryption {
+        match self.kms_key_id {
+            Some(_) => ServerSideEncryption::AwsKms,
+            None => ServerSideEncryption::Aes256,
+        }
+    }
+}
+
+pub async fn apply(client: &Client, bucket: &str, hardening: &Hardening) -> anyhow::Result<()> {
+    // ACLs are ignored with BucketOwnerEnforced, so everything in the bucket
+    // belongs to the bucket owner whoever uploaded it.
+    info!("Enforcing bucket owner ownership on {}", bucket);
+    let ownership = OwnershipControls::builder()
+        .rules(
+            OwnershipControlsRule::builder()
+                .object_ownership(ObjectOwnership::BucketOwnerEnforced)
+                .build(),
+        )
+        .build();
+    client
+        .put_bucket_ownership_controls()
+        .bucket(bucket)
+        .ownership_controls(ownership)
+        .send()
+        .await?;
+
+    info!("Blocking public access to {}", bucket);
+    let block = PublicAccessBlockConfiguration::builder()
+        .block_public_acls(true)
+        .ignore_public_acls(true)
+        .block_public_policy(true)
+        .restrict_public_buckets(true)
+        .build();
+    client
+        .put_public_access_block()
+        .bucket(bucket)
+        .public_access_block_configuration(block)
+        .send()
+        .await?;
+
+    info!("Turning on default encryption for {}", bucket);
+    let default = ServerSideEncryptionByDefault::builder()
+        .sse_algorithm(hardening.sse_algorithm())
+        .set_kms_master_key_id(hardening.kms_key_id.clone())
+        .build();
+    // Bucket keys cut the number of KMS requests, they make no difference to
+    // S3 managed keys.
+    let encryption = ServerSideEncryptionConfiguration::builder()
+        .rules(
+            ServerSideEncryptionRule::builder()
+                .apply_server_side_encryption_by_default(default)
+                .bucket_key_enabled(hardening.kms_key_id.is_some())
+                .build(),
+        )
+        .build();
+    client
+        .put_bucket_encryption()
+        .bucket(bucket)
+        .server_side_encryption_configuration(encryption)
+        .send()
+        .await?;
+
+    info!("Turning on versioning for {}", bucket);
+    client
+        .put_bucket_versioning()
+        .bucket(bucket)
+        .versioning_configuration(
+            VersioningConfiguration::builder()
+                .status(BucketVersioningStatus::Enabled)
+                .build(),
+        )
+        .send()
+        .await?;
+
+    info!("Denying requests that don't use TLS to {}", bucket);
+    client
+        .put_bucket_policy()
+        .bucket(bucket)
+        .policy(tls_only_policy(bucket).to_string())
+        .send()
+        .await?;
+
+    if let Some(log_bucket) = &hardening.log_bucket {
+        info!("Logging access to {} in {}", bucket, log_bucket);
+        let logging = BucketLoggingStatus::builder()
+            .logging_enabled(
+                LoggingEnabled::builder()
+                    .target_bucket(log_bucket)
+                    .target_prefix(&hardening.log_prefix)
+                    .build(),
+            )
+            .build();
+        client
+            .put_bucket_logging()
+            .bucket(bucket)
+            .bucket_logging_status(logging)
+            .send()
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn tls_only_policy(bucket: &str) -> Value {
+    let arn = format!("arn:aws:s3:::{}", bucket);
+    json!({
+        "Version": "2012-10-17",
+        "Statement": [{
+            "Sid": "DenyInsecureTransport",
+            "Effect": "Deny",
+            "Principal": "*",
+            "Action": "s3:*",
+            "Resource": [arn, format!("{}/*", arn)],
+            "Condition": { "Bool": { "aws:SecureTransport": "false" } }
+        }]
+    })
+}
+
+// The result of checking one setting on the bucket.
+pub struct Check {
+    pub setting: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(setting: &'static str, ok: bool, detail: impl Into<String>) -> Self {
+        Self {
+            setting,
+            ok,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mark = if self.ok { "ok" } else { "FAILED" };
+        write!(f, "{:<20} {:<6} {}", self.setting, mark, self.detail)
+    }
+}
+
+// Read each setting back from the bucket rather than trusting that the
+// requests in apply did what was asked.
+pub async fn verify(
+    client: &Client,
+    bucket: &str,
+    hardening: &Hardening,
+) -> anyhow::Result<Vec<Check>> {
+    let mut checks = vec![
+        ownership(client, bucket).await?,
+        public_access_block(client, bucket).await?,
+        encryption(client, bucket, hardening).await?,
+        versioning(client, bucket).await?,
+        tls_only(client, bucket).await?,
+    ];
+    if let Some(log_bucket) = &hardening.log_bucket {
+        checks.push(logging(client, bucket, log_bucket, &hardening.log_prefix).await?);
+    }
+    Ok(checks)
+}
+
+async fn ownership(client: &Client, bucket: &str) -> anyhow::Result<Check> {
+    let result = client
+        .get_bucket_ownership_controls()
+        .bucket(bucket)
+        .send()
+        .await;
+    let Some(resp) = none_if(result, ErrorKind::NotConfigured)? else {
+        return Ok(Check::new("object ownership", false, "not set"));
+    };
+
+    let ownership = resp
+        .ownership_controls()
+        .and_then(|c| c.rules())
+        .and_then(|rules| rules.first())
+        .and_then(|rule| rule.object_ownership());
+    Ok(match ownership {
+        Some(ObjectOwnership::BucketOwnerEnforced) => {
+            Check::new("object ownership", true, "BucketOwnerEnforced")
+        }
+        Some(other) => Check::new("object ownership", false, other.as_str()),
+        None => Check::new("object ownership", false, "not set"),
+    })
+}
+
+async fn public_access_block(client: &Client, bucket: &str) -> anyhow::Result<Check> {
+    let result = client.get_public_access_block().bucket(bucket).send().await;
+    let config = none_if(result, ErrorKind::NotConfigured)?
+        .and_then(|resp| resp.public_access_block_configuration().cloned());
+    let Some(config) = config else {
+        return Ok(Check::new("public access block", false, "not set"));
+    };
+
+    let settings = [
+        ("BlockPublicAcls", config.block_public_acls()),
+        ("IgnorePublicAcls", config.ignore_public_acls()),
+        ("BlockPublicPolicy", config.block_public_policy()),
+        ("RestrictPublicBuckets", config.restrict_public_buckets()),
+    ];
+    let off: Vec<_> = settings
+        .iter()
+        .filter(|(_, on)| !on)
+        .map(|(name, _)| *name)
+        .collect();
+    Ok(if off.is_empty() {
+        Check::new("public access block", true, "all four settings on")
+    } else {
+        Check::new(
+            "public access block",
+            false,
+            format!("off: {}", off.join(", ")),
+        )
+    })
+}
+
+async fn encryption(client: &Client, bucket: &str, hardening: &Hardening) -> anyhow::Result<Check> {
+    let result = client.get_bucket_encryption().bucket(bucket).send().await;
+    let default = none_if(result, ErrorKind::NotConfigured)?.and_then(|resp| {
+        resp.server_side_encryption_configuration()
+            .and_then(|c| c.rules())
+            .and_then(|rules| rules.first())
+            .and_then(|rule| rule.apply_server_side_encryption_by_default())
+            .cloned()
+    });
+    let Some(default) = default else {
+        return Ok(Check::new("default encryption", false, "not set"));
+    };
+
+    let algorithm = default
+        .sse_algorithm()
+        .map(|a| a.as_str())
+        .unwrap_or("none");
+    let detail = match default.kms_master_key_id() {
+        Some(key) => format!("{} with {}", algorithm, key),
+        None => algorithm.to_string(),
+    };
+    // The key ID can come back as an ARN even when it was set as an ID or
+    // alias, so only the algorithm is compared.
+    let ok = default.sse_algorithm() == Some(&hardening.sse_algorithm());
+    Ok(Check::new("default encryption", ok, detail))
+}
+
+async fn versioning(client: &Client, bucket: &str) -> anyhow::Result<Check> {
+    let resp = client.get_bucket_versioning().bucket(bucket).send().await?;
+    Ok(match resp.status() {
+        Some(BucketVersioningStatus::Enabled) => Check::new("versioning", true, "Enabled"),
+        Some(status) => Check::new("versioning", false, status.as_str()),
+        None => Check::new("versioning", false, "Disabled"),
+    })
+}
+
+// Any statement that denies requests where aws:SecureTransport is false will
+// do, it doesn't have to be the one apply added.
+async fn tls_only(client: &Client, bucket: &str) -> anyhow::Result<Check> {
+    let result = client.get_bucket_policy().bucket(bucket).send().await;
+    let Some(policy) = none_if(result, ErrorKind::NotConfigured)?
+        .and_then(|resp| resp.policy().map(str::to_string))
+    else {
+        return Ok(Check::new("TLS only policy", false, "no bucket policy"));
+    };
+
+    let policy: Value = serde_json::from_str(&policy)?;
+    let statements = match &policy["Statement"] {
+        Value::Array(statements) => statements.clone(),
+        statement => vec![statement.clone()],
+    };
+    let denies_insecure = statements.iter().any(|statement| {
+        let secure_transport = &statement["Condition"]["Bool"]["aws:SecureTransport"];
+        statement["Effect"] == "Deny" && (secure_transport == "false" || secure_transport == false)
+    });
+
+    Ok(if denies_insecure {
+        Check::new("TLS only policy", true, "requests without TLS are denied")
+    } else {
+        Check::new(
+            "TLS only policy",
+            false,
+            "no statement denies requests without TLS",
+        )
+    })
+}
+
+async fn logging(
+    client: &Client,
+    bucket: &str,
+    log_bucket: &str,
+    log_prefix: &str,
+) -> anyhow::Result<Check> {
+    let resp = client.get_bucket_logging().bucket(bucket).send().await?;
+    let Some(logging) = resp.logging_enabled() else {
+        return Ok(Check::new("access logging", false, "not enabled"));
+    };
+
+    let target = logging.target_bucket().unwrap_or_default();
+    let prefix = logging.target_prefix().unwrap_or_default();
+    let ok = target == log_bucket && prefix == log_prefix;
+    Ok(Check::new(
+        "access logging",
+        ok,
+        format!("to s3://{}/{}", target, prefix),
+    ))
+}
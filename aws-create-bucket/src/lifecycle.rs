@@ -0,0 +1,454 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use aws_common::{none_if, ErrorKind};
+use aws_sdk_s3::types::{
+    AbortIncompleteMultipartUpload, BucketLifecycleConfiguration, ExpirationStatus,
+    LifecycleExpiration, LifecycleRule, LifecycleRuleFilter, NoncurrentVersionExpiration,
+    Transition, TransitionStorageClass,
+};
+use aws_sdk_s3::Client;
+use serde::Deserialize;
+
+// The file format, for example:
+//
+//   [[rule]]
+//   id = "logs"
+//   prefix = "logs/"
+//   expire_days = 365
+//   abort_incomplete_days = 7
+//
+//   [[rule.transition]]
+//   days = 30
+//   storage_class = "STANDARD_IA"
+//
+//   [[rule.transition]]
+//   days = 90
+//   storage_class = "GLACIER"
+//
+//   [[rule]]
+//   id = "old-versions"
+//   enabled = false
+//   noncurrent_expire_days = 30
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LifecycleFile {
+    #[serde(rename = "rule", default)]
+    pub rules: Vec<Rule>,
+}
+
+// The parts of a lifecycle rule this manages, rules using anything else,
+// such as tag filters or expiry on a date, are left to the console.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub id: String,
+    // An empty prefix applies the rule to every object.
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    pub expire_days: Option<i32>,
+    #[serde(rename = "transition", default)]
+    pub transitions: Vec<RuleTransition>,
+    pub noncurrent_expire_days: Option<i32>,
+    pub abort_incomplete_days: Option<i32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleTransition {
+    pub days: i32,
+    pub storage_class: String,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl LifecycleFile {
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let input = std::fs::read_to_string(path)?;
+        let mut file: LifecycleFile = toml::from_str(&input)?;
+
+        let mut ids = Vec::new();
+        for rule in &mut file.rules {
+            if ids.contains(&rule.id) {
+                anyhow::bail!("rule '{}' is defined more than once", rule.id);
+            }
+            ids.push(rule.id.clone());
+            rule.validate()?;
+            // S3 returns transitions in day order.
+            rule.transitions.sort_by_key(|t| t.days);
+        }
+
+        Ok(file)
+    }
+}
+
+impl Rule {
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.id.is_empty() || self.id.len() > 255 {
+            anyhow::bail!("rule IDs must be between 1 and 255 characters");
+        }
+        if self.expire_days.is_none()
+            && self.transitions.is_empty()
+            && self.noncurrent_expire_days.is_none()
+            && self.abort_incomplete_days.is_none()
+        {
+            anyhow::bail!("rule '{}' doesn't do anything", self.id);
+        }
+
+        let days = self
+            .expire_days
+            .iter()
+            .chain(self.noncurrent_expire_days.iter())
+            .chain(self.abort_incomplete_days.iter())
+            .chain(self.transitions.iter().map(|t| &t.days));
+        for days in days {
+            if *days < 1 {
+                anyhow::bail!(
+                    "rule '{}' has {} days, it must be at least 1",
+                    self.id,
+                    days
+                );
+            }
+        }
+
+        for transition in &self.transitions {
+            if !TransitionStorageClass::values().contains(&transition.storage_class.as_str()) {
+                anyhow::bail!(
+                    "rule '{}' transitions to unknown storage class {}, expected one of {}",
+                    self.id,
+                    transition.storage_class,
+                    TransitionStorageClass::values().join(", ")
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = if self.prefix.is_empty() {
+            "all objects"
+        } else {
+            &self.prefix
+        };
+        let status = if self.enabled { "" } else { " (disabled)" };
+        writeln!(f, "{}: {}{}", self.id, prefix, status)?;
+
+        for transition in &self.transitions {
+            writeln!(
+                f,
+                "    to {} after {} days",
+                transition.storage_class, transition.days
+            )?;
+        }
+        if let Some(days) = self.expire_days {
+            writeln!(f, "    expire after {} days", days)?;
+        }
+        if let Some(days) = self.noncurrent_expire_days {
+            writeln!(f, "    expire noncurrent versions after {} days", days)?;
+        }
+        if let Some(days) = self.abort_incomplete_days {
+            writeln!(
+                f,
+                "    abort incomplete multipart uploads after {} days",
+                days
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// The SDK reports a number of days that isn't set as 0.
+fn days(days: i32) -> Option<i32> {
+    (days > 0).then_some(days)
+}
+
+impl TryFrom<&LifecycleRule> for Rule {
+    type Error = anyhow::Error;
+
+    fn try_from(rule: &LifecycleRule) -> Result<Self, Self::Error> {
+        let id = rule.id().unwrap_or_default().to_string();
+        let unsupported = |what: &str| anyhow::anyhow!("uses {}, which isn't supported", what);
+
+        // Old rules have the prefix on the rule rather than in a filter.
+        #[allow(deprecated)]
+        let prefix = match rule.filter() {
+            None => rule.prefix().unwrap_or_default().to_string(),
+            Some(LifecycleRuleFilter::Prefix(prefix)) => prefix.clone(),
+            Some(_) => return Err(unsupported("a tag, size or combined filter")),
+        };
+
+        let expire_days = match rule.expiration() {
+            None => None,
+            Some(expiration) if expiration.date().is_some() => {
+                return Err(unsupported("expiry on a date"))
+            }
+            Some(expiration) if expiration.expired_object_delete_marker() => {
+                return Err(unsupported("expired delete marker removal"))
+            }
+            Some(expiration) => days(expiration.days()),
+        };
+
+        let mut transitions = Vec::new();
+        for transition in rule.transitions().unwrap_or_default() {
+            if transition.date().is_some() {
+                return Err(unsupported("a transition on a date"));
+            }
+            transitions.push(RuleTransition {
+                days: transition.days(),
+                storage_class: transition
+                    .storage_class()
+                    .map(|c| c.as_str().to_string())
+                    .unwrap_or_default(),
+            });
+        }
+        transitions.sort_by_key(|t| t.days);
+
+        if rule
+            .noncurrent_version_transitions()
+            .is_some_and(|t| !t.is_empty())
+        {
+            return Err(unsupported("noncurrent version transitions"));
+        }
+        let noncurrent_expiration = rule.noncurrent_version_expiration();
+        if noncurrent_expiration.is_some_and(|e| e.newer_noncurrent_versions() > 0) {
+            return Err(unsupported("a number of newer noncurrent versions to keep"));
+        }
+
+        Ok(Rule {
+            id,
+            prefix,
+            enabled: rule.status() == Some(&ExpirationStatus::Enabled),
+            expire_days,
+            transitions,
+            noncurrent_expire_days: noncurrent_expiration.and_then(|e| days(e.noncurrent_days())),
+            abort_incomplete_days: rule
+                .abort_incomplete_multipart_upload()
+                .and_then(|a| days(a.days_after_initiation())),
+        })
+    }
+}
+
+impl From<&Rule> for LifecycleRule {
+    fn from(rule: &Rule) -> Self {
+        let status = if rule.enabled {
+            ExpirationStatus::Enabled
+        } else {
+            ExpirationStatus::Disabled
+        };
+        let transitions = rule
+            .transitions
+            .iter()
+            .map(|t| {
+                Transition::builder()
+                    .days(t.days)
+                    .storage_class(TransitionStorageClass::from(t.storage_class.as_str()))
+                    .build()
+            })
+            .collect();
+
+        LifecycleRule::builder()
+            .id(&rule.id)
+            .filter(LifecycleRuleFilter::Prefix(rule.prefix.clone()))
+            .status(status)
+            .set_expiration(
+                rule.expire_days
+                    .map(|days| LifecycleExpiration::builder().days(days).build()),
+            )
+            .set_transitions(Some(transitions))
+            .set_noncurrent_version_expiration(rule.noncurrent_expire_days.map(|days| {
+                NoncurrentVersionExpiration::builder()
+                    .noncurrent_days(days)
+                    .build()
+            }))
+            .set_abort_incomplete_multipart_upload(rule.abort_incomplete_days.map(|days| {
+                AbortIncompleteMultipartUpload::builder()
+                    .days_after_initiation(days)
+                    .build()
+            }))
+            .build()
+    }
+}
+
+pub enum Change {
+    Add(Rule),
+    Remove(Rule),
+    Update(Rule, Rule),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Add(rule) => write_prefixed(f, "+ ", rule),
+            Change::Remove(rule) => write_prefixed(f, "- ", rule),
+            Change::Update(live, desired) => {
+                write_prefixed(f, "- ", live)?;
+                write_prefixed(f, "+ ", desired)
+            }
+        }
+    }
+}
+
+fn write_prefixed(f: &mut fmt::Formatter<'_>, prefix: &str, rule: &Rule) -> fmt::Result {
+    for line in rule.to_string().lines() {
+        writeln!(f, "{}{}", prefix, line)?;
+    }
+    Ok(())
+}
+
+// Rules are matched by ID, the order of the rules doesn't matter to S3.
+pub fn diff(desired: &[Rule], live: &[Rule]) -> Vec<Change> {
+    let mut live: BTreeMap<&str, &Rule> = live.iter().map(|r| (r.id.as_str(), r)).collect();
+
+    let mut changes = Vec::new();
+    for rule in desired {
+        match live.remove(rule.id.as_str()) {
+            Some(existing) if existing != rule => {
+                changes.push(Change::Update(existing.clone(), rule.clone()))
+            }
+            Some(_) => {}
+            None => changes.push(Change::Add(rule.clone())),
+        }
+    }
+    for existing in live.into_values() {
+        changes.push(Change::Remove(existing.clone()));
+    }
+
+    changes
+}
+
+// The bucket's rules, split into those this can manage and those it can't
+// represent, which are left as they are.
+pub struct LiveRules {
+    pub rules: Vec<Rule>,
+    pub unmanaged: Vec<Unmanaged>,
+}
+
+pub struct Unmanaged {
+    pub rule: LifecycleRule,
+    pub reason: String,
+}
+
+impl Unmanaged {
+    fn id(&self) -> &str {
+        self.rule.id().unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Unmanaged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}: unmanaged, it {}", self.id(), self.reason)
+    }
+}
+
+pub async fn live_rules(client: &Client, bucket: &str) -> anyhow::Result<LiveRules> {
+    let result = client
+        .get_bucket_lifecycle_configuration()
+        .bucket(bucket)
+        .send()
+        .await;
+    let mut live = LiveRules {
+        rules: Vec::new(),
+        unmanaged: Vec::new(),
+    };
+    let Some(resp) = none_if(result, ErrorKind::NotConfigured)? else {
+        return Ok(live);
+    };
+
+    for rule in resp.rules().unwrap_or_default() {
+        match Rule::try_from(rule) {
+            Ok(managed) => live.rules.push(managed),
+            Err(err) => live.unmanaged.push(Unmanaged {
+                rule: rule.clone(),
+                reason: err.to_string(),
+            }),
+        }
+    }
+    Ok(live)
+}
+
+pub async fn show(client: &Client, bucket: &str) -> anyhow::Result<()> {
+    let live = live_rules(client, bucket).await?;
+    if live.rules.is_empty() && live.unmanaged.is_empty() {
+        println!("{} has no lifecycle rules", bucket);
+    }
+    for rule in live.rules {
+        print!("{}", rule);
+    }
+    for rule in live.unmanaged {
+        print!("{}", rule);
+    }
+    Ok(())
+}
+
+// Print the changes needed to make the bucket's rules match the file, and
+// make them unless this is a dry run.
+pub async fn apply_file(
+    client: &Client,
+    bucket: &str,
+    path: &Path,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let file = LifecycleFile::from_path(path)?;
+    let live = live_rules(client, bucket).await?;
+
+    for rule in &live.unmanaged {
+        if file.rules.iter().any(|r| r.id == rule.id()) {
+            anyhow::bail!(
+                "rule '{}' on {} can't be replaced from the file, it {}",
+                rule.id(),
+                bucket,
+                rule.reason
+            );
+        }
+        print!("{}", rule);
+    }
+
+    let changes = diff(&file.rules, &live.rules);
+    if changes.is_empty() {
+        println!("The lifecycle rules for {} are up to date", bucket);
+        return Ok(());
+    }
+    for change in &changes {
+        print!("{}", change);
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    // The configuration is replaced as a whole, so the unmanaged rules go back
+    // in with the file's, and S3 won't accept one with no rules.
+    let rules: Vec<LifecycleRule> = file
+        .rules
+        .iter()
+        .map(LifecycleRule::from)
+        .chain(live.unmanaged.into_iter().map(|u| u.rule))
+        .collect();
+    if rules.is_empty() {
+        client
+            .delete_bucket_lifecycle()
+            .bucket(bucket)
+            .send()
+            .await?;
+    } else {
+        let config = BucketLifecycleConfiguration::builder()
+            .set_rules(Some(rules))
+            .build();
+        client
+            .put_bucket_lifecycle_configuration()
+            .bucket(bucket)
+            .lifecycle_configuration(config)
+            .send()
+            .await?;
+    }
+    println!("Applied {} changes to {}", changes.len(), bucket);
+
+    Ok(())
+}
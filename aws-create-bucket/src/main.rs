@@ -1,6 +1,10 @@
+use std::path::PathBuf;
+
+use aws_common::s3;
 use aws_config::meta::region::RegionProviderChain;
 use aws_sdk_s3::types::{BucketLocationConstraint, CreateBucketConfiguration, Tag, Tagging};
-use aws_sdk_s3::{Client, Error};
+use aws_sdk_s3::Client;
+use clap::{Args, Parser, Subcommand};
 
 #[cfg(debug_assertions)]
 use env_logger::Env;
@@ -8,19 +12,210 @@ use log::info;
 
 use uuid::Uuid;
 
+use harden::Hardening;
+
+mod empty;
+mod harden;
+mod lifecycle;
+
+#[derive(Parser)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create and tag a bucket with a random name, then delete it.  This is
+    /// what happens without a command
+    Demo,
+    /// Create a bucket, optionally hardened
+    Create(CreateArgs),
+    /// Check that a bucket has the settings of the hardening profile
+    Verify(VerifyArgs),
+    /// Delete every object version and delete marker in a bucket, then the
+    /// bucket itself
+    Delete(DeleteArgs),
+    /// Manage a bucket's lifecycle rules with a TOML rules file
+    #[clap(subcommand)]
+    Lifecycle(LifecycleCommand),
+}
+
+#[derive(Subcommand)]
+enum LifecycleCommand {
+    /// Print the bucket's lifecycle rules
+    Show { bucket: String },
+    /// Show the changes apply would make to the bucket's lifecycle rules
+    Diff { bucket: String, file: PathBuf },
+    /// Replace the bucket's lifecycle rules with the ones in the file
+    Apply { bucket: String, file: PathBuf },
+}
+
+// The hardening settings only mean anything with --harden, rather than
+// quietly ignoring them.
+#[derive(Args)]
+#[clap(
+    mut_arg("kms_key_id", |arg| arg.requires("harden")),
+    mut_arg("log_bucket", |arg| arg.requires("harden"))
+)]
+struct CreateArgs {
+    /// Name of the bucket, a random name is used without this
+    bucket: Option<String>,
+    /// Apply the hardening profile: block public access, default encryption,
+    /// versioning, a TLS only bucket policy, and bucket owner enforced
+    /// object ownership, then check each setting
+    #[clap(long)]
+    harden: bool,
+    #[clap(flatten)]
+    hardening: HardeningArgs,
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    /// Name of the bucket
+    bucket: String,
+    #[clap(flatten)]
+    hardening: HardeningArgs,
+}
+
+#[derive(Args)]
+struct DeleteArgs {
+    /// Name of the bucket
+    bucket: String,
+    /// Count what would be deleted without deleting it
+    #[clap(long)]
+    dry_run: bool,
+    /// Don't ask for confirmation
+    #[clap(long)]
+    yes: bool,
+}
+
+#[derive(Args)]
+struct HardeningArgs {
+    /// Encrypt with this KMS key rather than S3 managed keys
+    #[clap(long)]
+    kms_key_id: Option<String>,
+    /// Write access logs to this bucket, which must be in the same region
+    /// and allow logging.s3.amazonaws.com to put objects
+    #[clap(long)]
+    log_bucket: Option<String>,
+    /// Prefix for the access logs
+    #[clap(long, default_value = "", requires = "log_bucket")]
+    log_prefix: String,
+}
+
+impl HardeningArgs {
+    fn hardening(&self) -> Hardening {
+        Hardening {
+            kms_key_id: self.kms_key_id.clone(),
+            log_bucket: self.log_bucket.clone(),
+            log_prefix: self.log_prefix.clone(),
+        }
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<(), Error> {
+async fn main() -> anyhow::Result<()> {
     #[cfg(not(debug_assertions))]
     env_logger::init();
 
     #[cfg(debug_assertions)]
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
+    let cli = Cli::parse();
+
     let region_provider = RegionProviderChain::default_provider().or_else("eu-west-1");
 
     let config = aws_config::from_env().region(region_provider).load().await;
     let client = Client::new(&config);
 
+    match cli.command.unwrap_or(Command::Demo) {
+        Command::Demo => demo(&client).await?,
+        Command::Create(args) => {
+            let bucket = args
+                .bucket
+                .unwrap_or_else(|| Uuid::new_v4().hyphenated().to_string());
+            info!("Creating bucket {}", bucket);
+            let region = s3::create_bucket(&client, &bucket).await?;
+            println!("Created bucket {} in {}", bucket, region);
+
+            if args.harden {
+                let hardening = args.hardening.hardening();
+                harden::apply(&client, &bucket, &hardening).await?;
+                verify(&client, &bucket, &hardening).await?;
+            }
+        }
+        Command::Verify(args) => {
+            let client = s3::client_for_bucket(&client, &args.bucket).await?;
+            verify(&client, &args.bucket, &args.hardening.hardening()).await?
+        }
+        Command::Delete(args) => {
+            let client = s3::client_for_bucket(&client, &args.bucket).await?;
+            delete(&client, &args).await?
+        }
+        Command::Lifecycle(LifecycleCommand::Show { bucket }) => {
+            let client = s3::client_for_bucket(&client, &bucket).await?;
+            lifecycle::show(&client, &bucket).await?
+        }
+        Command::Lifecycle(LifecycleCommand::Diff { bucket, file }) => {
+            let client = s3::client_for_bucket(&client, &bucket).await?;
+            lifecycle::apply_file(&client, &bucket, &file, true).await?
+        }
+        Command::Lifecycle(LifecycleCommand::Apply { bucket, file }) => {
+            let client = s3::client_for_bucket(&client, &bucket).await?;
+            lifecycle::apply_file(&client, &bucket, &file, false).await?
+        }
+    }
+
+    info!("All done.");
+    Ok(())
+}
+
+async fn verify(client: &Client, bucket: &str, hardening: &Hardening) -> anyhow::Result<()> {
+    let checks = harden::verify(client, bucket, hardening).await?;
+    for check in &checks {
+        println!("{}", check);
+    }
+
+    let failed = checks.iter().filter(|c| !c.ok).count();
+    if failed > 0 {
+        anyhow::bail!(
+            "{} of {} settings on {} are not hardened",
+            failed,
+            checks.len(),
+            bucket
+        );
+    }
+    Ok(())
+}
+
+async fn delete(client: &Client, args: &DeleteArgs) -> anyhow::Result<()> {
+    let contents = empty::count(client, &args.bucket).await?;
+    if args.dry_run {
+        println!(
+            "Would delete {} object versions ({} bytes), {} delete markers, and bucket {}",
+            contents.versions, contents.bytes, contents.delete_markers, args.bucket
+        );
+        return Ok(());
+    }
+
+    if !args.yes && !empty::confirm(&args.bucket, &contents)? {
+        println!("Not deleting {}", args.bucket);
+        return Ok(());
+    }
+
+    let deleted = empty::empty_bucket(client, &args.bucket).await?;
+    println!(
+        "Deleted {} object versions and delete markers from {}",
+        deleted, args.bucket
+    );
+
+    client.delete_bucket().bucket(&args.bucket).send().await?;
+    println!("Deleted bucket {}", args.bucket);
+    Ok(())
+}
+
+async fn demo(client: &Client) -> anyhow::Result<()> {
     let bucket_name = Uuid::new_v4();
 
     let constraint = BucketLocationConstraint::from("eu-west-1");
@@ -28,7 +223,7 @@ async fn main() -> Result<(), Error> {
         .location_constraint(constraint)
         .build();
 
-    info!("Creating bucket: {}", bucket_name.hyphenated().to_string());
+    info!("Creating bucket: {}", bucket_name.hyphenated());
     client
         .create_bucket()
         .bucket(bucket_name.hyphenated().to_string())
@@ -46,7 +241,7 @@ async fn main() -> Result<(), Error> {
         "Adding tag {}:{} to bucket {}",
         tag.key().unwrap(),
         tag.value().unwrap(),
-        bucket_name.hyphenated().to_string()
+        bucket_name.hyphenated()
     );
     client
         .put_bucket_tagging()
@@ -55,13 +250,12 @@ async fn main() -> Result<(), Error> {
         .send()
         .await?;
 
-    info!("Deleting bucket: {}", bucket_name.hyphenated().to_string());
+    info!("Deleting bucket: {}", bucket_name.hyphenated());
     client
         .delete_bucket()
         .bucket(bucket_name.hyphenated().to_string())
         .send()
         .await?;
 
-    info!("All done.");
     Ok(())
 }
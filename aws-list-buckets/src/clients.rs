@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use aws_types::region::Region;
+use aws_types::SdkConfig;
+
+// Requests about a bucket have to go to the region it's in, so keep an S3 and
+// CloudWatch client for each region we come across, shared by all the
+// buckets being inspected at the same time.
+pub struct Clients {
+    config: SdkConfig,
+    s3: Mutex<HashMap<String, aws_sdk_s3::Client>>,
+    cloudwatch: Mutex<HashMap<String, aws_sdk_cloudwatch::Client>>,
+}
+
+impl Clients {
+    pub fn new(config: &SdkConfig) -> Self {
+        Self {
+            config: config.clone(),
+            s3: Mutex::new(HashMap::new()),
+            cloudwatch: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn s3(&self, region: &str) -> aws_sdk_s3::Client {
+        let config = &self.config;
+        self.s3
+            .lock()
+            .expect("S3 client cache lock should never be poisoned")
+            .entry(region.to_string())
+            .or_insert_with(|| {
+                let conf = aws_sdk_s3::config::Builder::from(config)
+                    .region(Region::new(region.to_string()))
+                    .build();
+                aws_sdk_s3::Client::from_conf(conf)
+            })
+            .clone()
+    }
+
+    pub fn cloudwatch(&self, region: &str) -> aws_sdk_cloudwatch::Client {
+        let config = &self.config;
+        self.cloudwatch
+            .lock()
+            .expect("CloudWatch client cache lock should never be poisoned")
+            .entry(region.to_string())
+            .or_insert_with(|| {
+                let conf = aws_sdk_cloudwatch::config::Builder::from(config)
+                    .region(Region::new(region.to_string()))
+                    .build();
+                aws_sdk_cloudwatch::Client::from_conf(conf)
+            })
+            .clone()
+    }
+}
@@ -0,0 +1,38 @@
+use std::fmt;
+
+use aws_common::{classify, ErrorKind};
+use aws_sdk_s3::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
+use serde::Serialize;
+
+// Something about a bucket that couldn't be found out.
+#[derive(Clone, Debug, Serialize)]
+pub struct Problem {
+    pub check: &'static str,
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn new<E, R>(check: &'static str, err: &SdkError<E, R>) -> Self
+    where
+        E: ProvideErrorMetadata + std::error::Error + 'static,
+        R: fmt::Debug,
+    {
+        let message = match (err.code(), err.message()) {
+            (Some(code), Some(message)) => format!("{}: {}", code, message),
+            (Some(code), None) => code.to_string(),
+            _ => DisplayErrorContext(err).to_string(),
+        };
+        Self {
+            check,
+            kind: classify(err),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} ({})", self.check, self.kind, self.message)
+    }
+}
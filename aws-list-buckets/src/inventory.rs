@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use aws_common::{classify, s3, ErrorKind};
+use aws_sdk_s3::primitives::DateTimeFormat;
+use aws_sdk_s3::types::Bucket;
+use aws_sdk_s3::Client;
+use serde::Serialize;
+
+use crate::clients::Clients;
+use crate::errors::Problem;
+use crate::retry::with_backoff;
+use crate::size::{self, SizeSource};
+
+// Anything that couldn't be found out is None, with the reason in problems.
+#[derive(Debug, Serialize)]
+pub struct BucketInfo {
+    pub name: String,
+    pub region: Option<String>,
+    // RFC 3339, so it sorts as a string.
+    pub created: Option<String>,
+    pub size_bytes: Option<u64>,
+    pub objects: Option<u64>,
+    pub versioning: Option<String>,
+    pub public_access_block: Option<String>,
+    pub tags: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub problems: Vec<Problem>,
+}
+
+pub async fn inspect(
+    client: &Client,
+    clients: &Clients,
+    bucket: &Bucket,
+    size_source: SizeSource,
+) -> BucketInfo {
+    let name = bucket.name().unwrap_or_default().to_string();
+    let created = bucket
+        .creation_date()
+        .and_then(|d| d.fmt(DateTimeFormat::DateTime).ok());
+    let mut problems = Vec::new();
+
+    // Without the region the other requests will probably be redirected, but
+    // try them anyway in case the bucket is in the default region.
+    let region = check(&mut problems, bucket_region(client, &name).await);
+    let (s3, cloudwatch) = match &region {
+        Some(region) => (clients.s3(region), clients.cloudwatch(region)),
+        None => (
+            client.clone(),
+            clients.cloudwatch(&s3::client_region(client)),
+        ),
+    };
+
+    let size = check(
+        &mut problems,
+        size::bucket_size(&s3, &cloudwatch, &name, size_source).await,
+    );
+    let versioning = check(&mut problems, versioning(&s3, &name).await);
+    let public_access_block = check(&mut problems, public_access_block(&s3, &name).await);
+    let tags = check(&mut problems, tags(&s3, &name).await);
+
+    BucketInfo {
+        name,
+        region,
+        created,
+        size_bytes: size.and_then(|s| s.bytes),
+        objects: size.and_then(|s| s.objects),
+        versioning,
+        public_access_block,
+        tags,
+        problems,
+    }
+}
+
+fn check<T>(problems: &mut Vec<Problem>, result: Result<T, Problem>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(problem) => {
+            problems.push(problem);
+            None
+        }
+    }
+}
+
+async fn bucket_region(client: &Client, bucket: &str) -> Result<String, Problem> {
+    with_backoff(|| s3::bucket_region(client, bucket))
+        .await
+        .map_err(|err| Problem::new("region", &err))
+}
+
+// Enabled or Suspended, or Disabled for a bucket that has never had it on.
+async fn versioning(client: &Client, bucket: &str) -> Result<String, Problem> {
+    let resp = with_backoff(|| client.get_bucket_versioning().bucket(bucket).send())
+        .await
+        .map_err(|err| Problem::new("versioning", &err))?;
+    Ok(resp
+        .status()
+        .map(|s| s.as_str())
+        .unwrap_or("Disabled")
+        .to_string())
+}
+
+// "on" when all four settings block public access, "off" when none do.
+async fn public_access_block(client: &Client, bucket: &str) -> Result<String, Problem> {
+    let resp = match with_backoff(|| client.get_public_access_block().bucket(bucket).send()).await {
+        Ok(resp) => resp,
+        Err(err) if classify(&err) == ErrorKind::NotConfigured => return Ok("not set".to_string()),
+        Err(err) => return Err(Problem::new("public access block", &err)),
+    };
+
+    let Some(config) = resp.public_access_block_configuration() else {
+        return Ok("not set".to_string());
+    };
+    let settings = [
+        config.block_public_acls(),
+        config.ignore_public_acls(),
+        config.block_public_policy(),
+        config.restrict_public_buckets(),
+    ];
+    let on = settings.iter().filter(|s| **s).count();
+    Ok(match on {
+        4 => "on".to_string(),
+        0 => "off".to_string(),
+        n => format!("partial ({}/4)", n),
+    })
+}
+
+async fn tags(client: &Client, bucket: &str) -> Result<BTreeMap<String, String>, Problem> {
+    let resp = match with_backoff(|| client.get_bucket_tagging().bucket(bucket).send()).await {
+        Ok(resp) => resp,
+        // A bucket without tags is reported as an error.
+        Err(err) if classify(&err) == ErrorKind::NotConfigured => return Ok(BTreeMap::new()),
+        Err(err) => return Err(Problem::new("tags", &err)),
+    };
+
+    Ok(resp
+        .tag_set()
+        .unwrap_or_default()
+        .iter()
+        .map(|tag| {
+            (
+                tag.key().unwrap_or_default().to_string(),
+                tag.value().unwrap_or_default().to_string(),
+            )
+        })
+        .collect())
+}
@@ -1,36 +1,201 @@
+use std::collections::BTreeMap;
+
+use aws_common::ErrorKind;
 use aws_config::meta::region::RegionProviderChain;
-use aws_sdk_s3::{Client, Error};
+use aws_sdk_s3::Client;
+use clap::{Parser, ValueEnum};
+use futures::StreamExt;
+
+use clients::Clients;
+use inventory::BucketInfo;
+use size::SizeSource;
+
+mod clients;
+mod errors;
+mod inventory;
+mod retry;
+mod size;
+
+#[derive(Parser)]
+struct Cli {
+    /// How to find each bucket's size and object count
+    #[clap(long, value_enum, default_value = "auto")]
+    size: SizeSource,
+    /// Column to sort the buckets by
+    #[clap(long, value_enum, default_value = "name")]
+    sort: SortKey,
+    /// Sort in descending order
+    #[clap(long)]
+    reverse: bool,
+    /// Print the buckets as JSON rather than a table
+    #[clap(long)]
+    json: bool,
+    /// Number of buckets to inspect at the same time
+    #[clap(long, default_value_t = 8)]
+    concurrency: usize,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SortKey {
+    Name,
+    Region,
+    Created,
+    Size,
+    Objects,
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Error> {
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
     let region_provider = RegionProviderChain::default_provider().or_else("eu-west-1");
 
     let config = aws_config::from_env().region(region_provider).load().await;
     let client = Client::new(&config);
+    let clients = Clients::new(&config);
 
     let resp = client.list_buckets().send().await?;
     let buckets = resp.buckets().unwrap_or_default();
 
-    for bucket in buckets {
-        println!("Bucket: {}", bucket.name().unwrap_or("Unamed"));
-        let tag_res = client
-            .get_bucket_tagging()
-            .bucket(bucket.name().unwrap_or_default())
-            .send()
-            .await;
-
-        match tag_res {
-            Ok(resp) => {
-                let tag_set = resp.tag_set().unwrap_or_default();
-                for tag in tag_set {
-                    let key = tag.key().unwrap_or_default();
-                    let value = tag.value().unwrap_or_default();
-                    println!("\tTag Name: {}, Tag Value: {}", key, value);
-                }
-            }
-            Err(_) => continue, // Should really match this to NoSuchTagSet
-        }
+    // buffered, unlike buffer_unordered, keeps the results in the order of
+    // the buckets however long each one takes.
+    let mut inventory: Vec<BucketInfo> = futures::stream::iter(buckets)
+        .map(|bucket| inventory::inspect(&client, &clients, bucket, cli.size))
+        .buffered(cli.concurrency.max(1))
+        .collect()
+        .await;
+
+    sort(&mut inventory, cli.sort);
+    if cli.reverse {
+        inventory.reverse();
+    }
+
+    if cli.json {
+        println!("{}", serde_json::to_string_pretty(&inventory)?);
+    } else {
+        print_table(&inventory);
     }
+    print_problems(&inventory);
 
     Ok(())
 }
+
+// Ties, and buckets without a size, are ordered by name.
+fn sort(inventory: &mut [BucketInfo], key: SortKey) {
+    inventory.sort_by(|a, b| {
+        let order = match key {
+            SortKey::Name => std::cmp::Ordering::Equal,
+            SortKey::Region => a.region.cmp(&b.region),
+            SortKey::Created => a.created.cmp(&b.created),
+            SortKey::Size => a.size_bytes.cmp(&b.size_bytes),
+            SortKey::Objects => a.objects.cmp(&b.objects),
+        };
+        order.then_with(|| a.name.cmp(&b.name))
+    });
+}
+
+fn print_table(inventory: &[BucketInfo]) {
+    let header = [
+        "NAME",
+        "REGION",
+        "CREATED",
+        "SIZE",
+        "OBJECTS",
+        "VERSIONING",
+        "PUBLIC ACCESS BLOCK",
+        "TAGS",
+    ];
+    let rows: Vec<[String; 8]> = inventory
+        .iter()
+        .map(|b| {
+            let tags: Option<Vec<String>> = b
+                .tags
+                .as_ref()
+                .map(|t| t.iter().map(|(k, v)| format!("{}={}", k, v)).collect());
+            [
+                b.name.clone(),
+                unknown(&b.region),
+                unknown(&b.created),
+                b.size_bytes
+                    .map(human_size)
+                    .unwrap_or_else(|| "-".to_string()),
+                b.objects
+                    .map(|o| o.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                unknown(&b.versioning),
+                unknown(&b.public_access_block),
+                tags.map(|t| t.join(",")).unwrap_or_else(|| "?".to_string()),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let line = |cells: Vec<&str>| {
+        let cells: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
+    };
+    line(header.to_vec());
+    for row in &rows {
+        line(row.iter().map(String::as_str).collect());
+    }
+}
+
+fn unknown(value: &Option<String>) -> String {
+    value.clone().unwrap_or_else(|| "?".to_string())
+}
+
+// Summarise the buckets that couldn't be fully inspected, grouped by why, on
+// stderr so that it doesn't get mixed up with JSON output.
+fn print_problems(inventory: &[BucketInfo]) {
+    let mut by_kind: BTreeMap<ErrorKind, Vec<String>> = BTreeMap::new();
+    for bucket in inventory {
+        for problem in &bucket.problems {
+            by_kind.entry(problem.kind).or_default().push(format!(
+                "{}: {}: {}",
+                bucket.name, problem.check, problem.message
+            ));
+        }
+    }
+    if by_kind.is_empty() {
+        return;
+    }
+
+    let count = inventory.iter().filter(|b| !b.problems.is_empty()).count();
+    eprintln!();
+    eprintln!(
+        "{} of {} buckets could not be fully inspected:",
+        count,
+        inventory.len()
+    );
+    for (kind, problems) in by_kind {
+        eprintln!("  {}:", kind);
+        for problem in problems {
+            eprintln!("    {}", problem);
+        }
+    }
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
@@ -0,0 +1,33 @@
+use std::future::Future;
+use std::time::Duration;
+
+use aws_common::{classify, Backoff, ErrorKind};
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use rand::Rng;
+
+const MAX_RETRIES: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+// The SDK already retries a few times, but with many requests in flight S3
+// can keep saying SlowDown, so back off for longer before giving up.  The
+// jitter stops every waiting request retrying at the same moment.
+pub async fn with_backoff<T, E, R, F, Fut>(mut request: F) -> Result<T, SdkError<E, R>>
+where
+    E: ProvideErrorMetadata,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, SdkError<E, R>>>,
+{
+    let mut backoff = Backoff::starting_at(INITIAL_BACKOFF);
+    let mut attempt = 0;
+    loop {
+        match request().await {
+            Err(err) if classify(&err) == ErrorKind::Transient && attempt < MAX_RETRIES => {
+                attempt += 1;
+                let delay = backoff.next_delay();
+                let jitter = rand::thread_rng().gen_range(0..=delay.as_millis() as u64 / 2);
+                tokio::time::sleep(delay + Duration::from_millis(jitter)).await;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aws_sdk_cloudwatch::primitives::DateTime;
+use aws_sdk_cloudwatch::types::{Dimension, DimensionFilter, Statistic};
+use clap::ValueEnum;
+
+use crate::errors::Problem;
+use crate::retry::with_backoff;
+
+// S3 publishes storage metrics to CloudWatch once a day.
+const DAY: i64 = 24 * 60 * 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SizeSource {
+    /// CloudWatch storage metrics, falling back to listing the objects
+    Auto,
+    /// CloudWatch storage metrics, free but up to a couple of days old
+    Cloudwatch,
+    /// List every object, exact but slow for large buckets
+    Listing,
+    /// Don't find the size
+    None,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Size {
+    pub bytes: Option<u64>,
+    pub objects: Option<u64>,
+}
+
+pub async fn bucket_size(
+    s3: &aws_sdk_s3::Client,
+    cloudwatch: &aws_sdk_cloudwatch::Client,
+    bucket: &str,
+    source: SizeSource,
+) -> Result<Size, Problem> {
+    match source {
+        SizeSource::None => Ok(Size::default()),
+        SizeSource::Listing => listed_size(s3, bucket).await,
+        SizeSource::Cloudwatch => metric_size(cloudwatch, bucket).await,
+        SizeSource::Auto => match metric_size(cloudwatch, bucket).await {
+            Ok(size) if size.bytes.is_some() && size.objects.is_some() => Ok(size),
+            // New or empty buckets have no metrics yet, and not everyone can
+            // read CloudWatch.
+            _ => listed_size(s3, bucket).await,
+        },
+    }
+}
+
+async fn listed_size(client: &aws_sdk_s3::Client, bucket: &str) -> Result<Size, Problem> {
+    let (mut bytes, mut objects) = (0, 0);
+    let mut token = None;
+    loop {
+        let resp = with_backoff(|| {
+            client
+                .list_objects_v2()
+                .bucket(bucket)
+                .set_continuation_token(token.clone())
+                .send()
+        })
+        .await
+        .map_err(|err| Problem::new("size", &err))?;
+
+        for object in resp.contents().unwrap_or_default() {
+            bytes += object.size() as u64;
+            objects += 1;
+        }
+
+        token = resp.next_continuation_token().map(str::to_string);
+        if !resp.is_truncated() {
+            break;
+        }
+    }
+
+    Ok(Size {
+        bytes: Some(bytes),
+        objects: Some(objects),
+    })
+}
+
+// BucketSizeBytes is published separately for each storage class in the
+// bucket, so add up every one there is.  This includes the overhead S3
+// charges for per object in some classes, such as Glacier.
+async fn metric_size(client: &aws_sdk_cloudwatch::Client, bucket: &str) -> Result<Size, Problem> {
+    let mut bytes = None;
+    for storage_type in storage_types(client, bucket).await? {
+        if let Some(b) = latest_metric(client, bucket, "BucketSizeBytes", &storage_type).await? {
+            *bytes.get_or_insert(0.0) += b;
+        }
+    }
+    let objects = latest_metric(client, bucket, "NumberOfObjects", "AllStorageTypes").await?;
+    Ok(Size {
+        bytes: bytes.map(|b| b as u64),
+        objects: objects.map(|o| o as u64),
+    })
+}
+
+// The storage classes there are BucketSizeBytes metrics for.
+async fn storage_types(
+    client: &aws_sdk_cloudwatch::Client,
+    bucket: &str,
+) -> Result<Vec<String>, Problem> {
+    let mut storage_types = Vec::new();
+    let mut token = None;
+    loop {
+        let resp = with_backoff(|| {
+            client
+                .list_metrics()
+                .namespace("AWS/S3")
+                .metric_name("BucketSizeBytes")
+                .dimensions(
+                    DimensionFilter::builder()
+                        .name("BucketName")
+                        .value(bucket)
+                        .build(),
+                )
+                .set_next_token(token.clone())
+                .send()
+        })
+        .await
+        .map_err(|err| Problem::new("size metrics", &err))?;
+
+        for metric in resp.metrics().unwrap_or_default() {
+            let storage_type = metric
+                .dimensions()
+                .unwrap_or_default()
+                .iter()
+                .find(|d| d.name() == Some("StorageType"))
+                .and_then(|d| d.value());
+            if let Some(storage_type) = storage_type {
+                storage_types.push(storage_type.to_string());
+            }
+        }
+
+        token = resp.next_token().map(str::to_string);
+        if token.is_none() {
+            break;
+        }
+    }
+
+    Ok(storage_types)
+}
+
+async fn latest_metric(
+    client: &aws_sdk_cloudwatch::Client,
+    bucket: &str,
+    metric: &str,
+    storage_type: &str,
+) -> Result<Option<f64>, Problem> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+
+    let request = client
+        .get_metric_statistics()
+        .namespace("AWS/S3")
+        .metric_name(metric)
+        .dimensions(
+            Dimension::builder()
+                .name("BucketName")
+                .value(bucket)
+                .build(),
+        )
+        .dimensions(
+            Dimension::builder()
+                .name("StorageType")
+                .value(storage_type)
+                .build(),
+        )
+        .start_time(DateTime::from_secs(now - 3 * DAY))
+        .end_time(DateTime::from_secs(now))
+        .period(DAY as i32)
+        .statistics(Statistic::Average);
+    let resp = with_backoff(|| request.clone().send())
+        .await
+        .map_err(|err| Problem::new("size metrics", &err))?;
+
+    let latest = resp
+        .datapoints()
+        .unwrap_or_default()
+        .iter()
+        .max_by_key(|d| d.timestamp().map(|t| t.secs()))
+        .and_then(|d| d.average());
+    Ok(latest)
+}
@@ -1,33 +1,150 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use aws_common::iam::wait_for_instance_profile;
 use aws_config::meta::region::RegionProviderChain;
+use aws_sdk_iam::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_iam::Client;
+use clap::{Args, Parser, Subcommand};
+
+use iam_policy::s3::{self, s3_policy, S3Grant};
+use iam_policy::simulate::{self, Decision, Request};
+use iam_policy::{PolicyDocument, PolicyKind};
+
+const INLINE_POLICY_NAME: &str = "S3AccessPolicy";
+const PROPAGATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Parser)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+    /// Name of the Role and of the Instance Profile
+    #[clap(long, global = true, default_value = "TestInstanceProfile")]
+    name: String,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create the Role, its policies, and the Instance Profile
+    Create(CreateArgs),
+    /// Attach managed policies to the Role
+    Attach(AttachArgs),
+    /// List the policies that apply to the Role
+    List(ListArgs),
+    /// Remove the policies, the Role, and the Instance Profile
+    Delete,
+    /// Check whether the Role's policies allow an action on a resource
+    Simulate(SimulateArgs),
+}
+
+#[derive(Args)]
+struct CreateArgs {
+    /// S3 access for the role as BUCKET[/PREFIX][:ACCESS,...] where ACCESS is
+    /// read, write or list, e.g. "renders/frames:read,list".  Without ACCESS
+    /// the role can read, write and list.
+    #[clap(long = "s3", required = true)]
+    grants: Vec<S3Grant>,
+    /// ARN of an AWS or customer managed policy to attach, e.g.
+    /// arn:aws:iam::aws:policy/AmazonSSMManagedInstanceCore
+    #[clap(long = "managed-policy")]
+    managed_policies: Vec<String>,
+    /// Print the generated S3 policy and exit without creating anything
+    #[clap(long)]
+    print_policy: bool,
+}
 
-use aws_sdk_iam::{Client, Error};
+#[derive(Args)]
+struct AttachArgs {
+    #[clap(required = true)]
+    policy_arns: Vec<String>,
+}
+
+#[derive(Args)]
+struct ListArgs {
+    /// Print each policy document as well as its name
+    #[clap(long)]
+    documents: bool,
+}
+
+#[derive(Args)]
+struct SimulateArgs {
+    /// Action to check, e.g. s3:GetObject
+    action: String,
+    /// ARN of the resource, e.g. arn:aws:s3:::renders/frames/0001.png
+    resource: String,
+    /// Simulate the S3 policy that create would generate for these grants
+    #[clap(long = "s3")]
+    grants: Vec<S3Grant>,
+    /// Simulate a policy document read from a local JSON file
+    #[clap(long = "policy-file")]
+    policy_files: Vec<PathBuf>,
+    /// Simulate the policies currently attached to the Role
+    #[clap(long)]
+    from_role: bool,
+    /// Condition key and value for the request as KEY=VALUE, e.g. s3:prefix=frames/
+    #[clap(long = "context", value_parser = parse_context)]
+    context: Vec<(String, String)>,
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Error> {
+async fn main() -> anyhow::Result<ExitCode> {
+    let cli = Cli::parse();
+
+    if let Command::Create(args) = &cli.command {
+        if args.print_policy {
+            println!("{}", s3_policy(&args.grants));
+            return Ok(ExitCode::SUCCESS);
+        }
+    }
+    if let Command::Simulate(args) = &cli.command {
+        if !args.from_role {
+            let decision = print_simulation(&local_policies(args)?, args)?;
+            return Ok(exit_code(decision));
+        }
+    }
+
     let region_provider = RegionProviderChain::default_provider().or_else("eu-west-1");
 
     let config = aws_config::from_env().region(region_provider).load().await;
     let client = Client::new(&config);
 
-    let trust_policy = r#"{
-    "Version": "2012-10-17",
-    "Statement": [
-        {
-            "Sid": "EC2AssumeRole",
-            "Effect": "Allow",
-            "Principal": {
-                "Service": "ec2.amazonaws.com"
-            },
-            "Action": "sts:AssumeRole"
+    match cli.command {
+        Command::Create(args) => create(&client, &cli.name, &args).await?,
+        Command::Attach(args) => attach_policies(&client, &cli.name, &args.policy_arns).await?,
+        Command::List(args) => list_policies(&client, &cli.name, args.documents).await?,
+        Command::Delete => delete(&client, &cli.name).await?,
+        Command::Simulate(args) => {
+            let mut policies = local_policies(&args)?;
+            policies.extend(role_policies(&client, &cli.name).await?);
+            let decision = print_simulation(&policies, &args)?;
+            return Ok(exit_code(decision));
         }
-    ]
-}"#;
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+// Anything but allowed is a failure, so scripts can check the result.
+fn exit_code(decision: Decision) -> ExitCode {
+    if decision == Decision::Allowed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+async fn create(client: &Client, name: &str, args: &CreateArgs) -> anyhow::Result<()> {
+    let trust_policy = PolicyDocument::assume_role_for_service("ec2.amazonaws.com");
+    let policy = s3_policy(&args.grants);
+    check_policy("trust", &trust_policy, PolicyKind::Trust)?;
+    check_policy("S3 access", &policy, PolicyKind::Identity)?;
 
     let resp = client
         .create_role()
-        .role_name("TestInstanceProfile")
-        .description("Allow Listing, Putting, and Getting objects from a specific S3 bucket")
-        .assume_role_policy_document(trust_policy)
+        .role_name(name)
+        .description(s3::describe(&args.grants))
+        .assume_role_policy_document(trust_policy.to_json())
         .send()
         .await?;
 
@@ -35,36 +152,20 @@ async fn main() -> Result<(), Error> {
     let role_name = role.role_name().expect("should always get a Role name");
     println!("Created role {}", role_name);
 
-    let policy = r#"{
-    "Version": "2012-10-17",
-    "Statement": [
-        {
-            "Sid": "ListObjectsInBucket",
-            "Effect": "Allow",
-            "Action": ["s3:ListBucket"],
-            "Resource": ["arn:aws:s3:::bucket-name"]
-        },
-        {
-            "Sid": "AllObjectActions",
-            "Effect": "Allow",
-            "Action": ["s3:PutObject", "s3:GetObject"],
-            "Resource": ["arn:aws:s3:::bucket-name/*"]
-        }
-    ]
-}"#;
-
     client
         .put_role_policy()
         .role_name(role_name)
-        .policy_name("S3AccessPolicy")
-        .policy_document(policy)
+        .policy_name(INLINE_POLICY_NAME)
+        .policy_document(policy.to_json())
         .send()
         .await?;
     println!("Added policy to role: {}", role_name);
 
+    attach_policies(client, role_name, &args.managed_policies).await?;
+
     let resp = client
         .create_instance_profile()
-        .instance_profile_name("TestInstanceProfile")
+        .instance_profile_name(name)
         .send()
         .await?;
 
@@ -86,5 +187,285 @@ async fn main() -> Result<(), Error> {
         role_name, profile_name
     );
 
+    wait_for_instance_profile(client, profile_name, PROPAGATION_TIMEOUT).await?;
+
+    Ok(())
+}
+
+async fn attach_policies(client: &Client, role_name: &str, arns: &[String]) -> anyhow::Result<()> {
+    for arn in arns {
+        if !iam_policy::validate::valid_arn(arn) || !arn.contains(":policy/") {
+            anyhow::bail!("'{}' is not a managed policy ARN", arn);
+        }
+
+        client
+            .attach_role_policy()
+            .role_name(role_name)
+            .policy_arn(arn)
+            .send()
+            .await?;
+        println!("Attached {} to role: {}", arn, role_name);
+    }
+
+    Ok(())
+}
+
+async fn list_policies(client: &Client, role_name: &str, documents: bool) -> anyhow::Result<()> {
+    println!("Inline policies:");
+    for policy_name in inline_policies(client, role_name).await? {
+        println!("    {}", policy_name);
+        if documents {
+            let resp = client
+                .get_role_policy()
+                .role_name(role_name)
+                .policy_name(&policy_name)
+                .send()
+                .await?;
+            let document = resp
+                .policy_document()
+                .expect("an inline policy should always have a document");
+            print_document(document)?;
+        }
+    }
+
+    println!("Managed policies:");
+    for (policy_name, arn) in attached_policies(client, role_name).await? {
+        println!("    {} ({})", policy_name, arn);
+        if documents {
+            print_document(&managed_policy_document(client, &arn).await?)?;
+        }
+    }
+
+    Ok(())
+}
+
+// The policies named on the command line, in the order they were given.
+fn local_policies(args: &SimulateArgs) -> anyhow::Result<Vec<(String, PolicyDocument)>> {
+    let mut policies = Vec::new();
+    if !args.grants.is_empty() {
+        policies.push((INLINE_POLICY_NAME.to_string(), s3_policy(&args.grants)));
+    }
+    for path in &args.policy_files {
+        let document = std::fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        policies.push((path.display().to_string(), document));
+    }
+    Ok(policies)
+}
+
+// Every inline and managed policy document that applies to the Role.
+async fn role_policies(
+    client: &Client,
+    role_name: &str,
+) -> anyhow::Result<Vec<(String, PolicyDocument)>> {
+    let mut policies = Vec::new();
+    for policy_name in inline_policies(client, role_name).await? {
+        let resp = client
+            .get_role_policy()
+            .role_name(role_name)
+            .policy_name(&policy_name)
+            .send()
+            .await?;
+        let document = resp
+            .policy_document()
+            .expect("an inline policy should always have a document");
+        policies.push((policy_name, PolicyDocument::from_url_encoded(document)?));
+    }
+
+    for (policy_name, arn) in attached_policies(client, role_name).await? {
+        let document = managed_policy_document(client, &arn).await?;
+        policies.push((policy_name, PolicyDocument::from_url_encoded(&document)?));
+    }
+
+    Ok(policies)
+}
+
+fn print_simulation(
+    policies: &[(String, PolicyDocument)],
+    args: &SimulateArgs,
+) -> anyhow::Result<Decision> {
+    if policies.is_empty() {
+        anyhow::bail!("nothing to simulate, use --s3, --policy-file or --from-role");
+    }
+
+    let mut request = Request::new(&args.action, &args.resource);
+    for (key, value) in &args.context {
+        request = request.context(key, value);
+    }
+
+    let evaluation = simulate::evaluate(policies, &request);
+    println!("{} on {}: {}", args.action, args.resource, evaluation);
+    for note in &evaluation.notes {
+        println!("    note: {}", note);
+    }
+
+    Ok(evaluation.decision)
+}
+
+fn parse_context(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("'{}' should be KEY=VALUE", s)),
+    }
+}
+
+// Tear down in the reverse order to creation, ignoring anything that has
+// already gone so that a partly created or partly deleted setup can be cleaned up.
+async fn delete(client: &Client, name: &str) -> anyhow::Result<()> {
+    let result = client
+        .remove_role_from_instance_profile()
+        .instance_profile_name(name)
+        .role_name(name)
+        .send()
+        .await;
+    if ignore_missing(result)?.is_some() {
+        println!("Removed Role {} from Instance Profile {}", name, name);
+    }
+
+    let result = client
+        .delete_instance_profile()
+        .instance_profile_name(name)
+        .send()
+        .await;
+    if ignore_missing(result)?.is_some() {
+        println!("Deleted Instance Profile {}", name);
+    }
+
+    let role = client.get_role().role_name(name).send().await;
+    if ignore_missing(role)?.is_none() {
+        println!("Role {} does not exist", name);
+        return Ok(());
+    }
+
+    for (_, arn) in attached_policies(client, name).await? {
+        client
+            .detach_role_policy()
+            .role_name(name)
+            .policy_arn(&arn)
+            .send()
+            .await?;
+        println!("Detached {} from role: {}", arn, name);
+    }
+
+    for policy_name in inline_policies(client, name).await? {
+        client
+            .delete_role_policy()
+            .role_name(name)
+            .policy_name(&policy_name)
+            .send()
+            .await?;
+        println!("Deleted policy {} from role: {}", policy_name, name);
+    }
+
+    client.delete_role().role_name(name).send().await?;
+    println!("Deleted role {}", name);
+
+    Ok(())
+}
+
+async fn inline_policies(client: &Client, role_name: &str) -> anyhow::Result<Vec<String>> {
+    let mut names = Vec::new();
+    let mut marker = None;
+    loop {
+        let resp = client
+            .list_role_policies()
+            .role_name(role_name)
+            .set_marker(marker)
+            .send()
+            .await?;
+
+        names.extend(resp.policy_names().unwrap_or_default().iter().cloned());
+
+        marker = resp.marker().map(str::to_string);
+        if !resp.is_truncated() {
+            break;
+        }
+    }
+
+    Ok(names)
+}
+
+// Name and ARN of each managed policy attached to the role.
+async fn attached_policies(
+    client: &Client,
+    role_name: &str,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let mut policies = Vec::new();
+    let mut marker = None;
+    loop {
+        let resp = client
+            .list_attached_role_policies()
+            .role_name(role_name)
+            .set_marker(marker)
+            .send()
+            .await?;
+
+        for policy in resp.attached_policies().unwrap_or_default() {
+            policies.push((
+                policy.policy_name().unwrap_or_default().to_string(),
+                policy
+                    .policy_arn()
+                    .expect("an attached policy should always have an ARN")
+                    .to_string(),
+            ));
+        }
+
+        marker = resp.marker().map(str::to_string);
+        if !resp.is_truncated() {
+            break;
+        }
+    }
+
+    Ok(policies)
+}
+
+// The URL encoded document of the default version of a managed policy.
+async fn managed_policy_document(client: &Client, arn: &str) -> anyhow::Result<String> {
+    let resp = client.get_policy().policy_arn(arn).send().await?;
+    let version = resp
+        .policy()
+        .and_then(|p| p.default_version_id())
+        .expect("a managed policy should always have a default version");
+
+    let resp = client
+        .get_policy_version()
+        .policy_arn(arn)
+        .version_id(version)
+        .send()
+        .await?;
+
+    Ok(resp
+        .policy_version()
+        .and_then(|v| v.document())
+        .expect("a policy version should always have a document")
+        .to_string())
+}
+
+fn print_document(encoded: &str) -> anyhow::Result<()> {
+    let document = PolicyDocument::from_url_encoded(encoded)?;
+    for line in document.to_json().lines() {
+        println!("        {}", line);
+    }
+    Ok(())
+}
+
+fn ignore_missing<T, E: ProvideErrorMetadata, R>(
+    result: Result<T, SdkError<E, R>>,
+) -> Result<Option<T>, SdkError<E, R>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.code() == Some("NoSuchEntity") => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn check_policy(name: &str, policy: &PolicyDocument, kind: PolicyKind) -> anyhow::Result<()> {
+    for issue in policy.validate(kind) {
+        println!("{} policy {}", name, issue);
+    }
+    if policy.has_errors(kind) {
+        anyhow::bail!("{} policy is not valid", name);
+    }
     Ok(())
 }
@@ -1,17 +1,48 @@
+use aws_common::iam::wait_for_instance_profile;
+use aws_common::Backoff;
 use aws_config::meta::region::RegionProviderChain;
+use aws_sdk_ec2::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
+use aws_sdk_ec2::operation::run_instances::RunInstancesError;
 use aws_sdk_ec2::types::{
     AttributeBooleanValue, Filter, IamInstanceProfileSpecification, Instance, InstanceType,
     ResourceType, ShutdownBehavior, Tag, TagSpecification,
 };
 use aws_sdk_ec2::Client as Ec2Client;
 use aws_sdk_iam::Client as IamClient;
+use clap::Parser;
+
+use iam_policy::s3::{self, s3_policy, S3Grant};
+use iam_policy::{PolicyDocument, PolicyKind};
 
 use base64::{engine::general_purpose, Engine as _};
 
+use std::time::{Duration, Instant};
 use std::{thread, time};
 
+const PROPAGATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Parser)]
+struct Cli {
+    /// S3 access for the instance as BUCKET[/PREFIX][:ACCESS,...] where
+    /// ACCESS is read, write or list, e.g. "renders/frames:read,list".
+    /// Without ACCESS the instance can read, write and list.
+    #[clap(long = "s3", required = true)]
+    grants: Vec<S3Grant>,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    // Check the policy before creating anything.
+    let policy = s3_policy(&cli.grants);
+    for issue in policy.validate(PolicyKind::Identity) {
+        println!("S3 access policy {}", issue);
+    }
+    if policy.has_errors(PolicyKind::Identity) {
+        anyhow::bail!("S3 access policy is not valid");
+    }
+
     let region_provider = RegionProviderChain::default_provider().or_else("eu-west-1");
 
     let config = aws_config::from_env().region(region_provider).load().await;
@@ -30,25 +61,13 @@ async fn main() -> anyhow::Result<()> {
     ];
 
     // Create the Role
-    let trust_policy = r#"{
-        "Version": "2012-10-17",
-        "Statement": [
-            {
-                "Sid": "EC2AssumeRole",
-                "Effect": "Allow",
-                "Principal": {
-                    "Service": "ec2.amazonaws.com"
-                },
-                "Action": "sts:AssumeRole"
-            }
-        ]
-    }"#;
+    let trust_policy = PolicyDocument::assume_role_for_service("ec2.amazonaws.com");
 
     let resp = iam_client
         .create_role()
         .role_name("TestInstanceProfile")
-        .description("Allow Listing, Putting, and Getting objects from a specific S3 bucket")
-        .assume_role_policy_document(trust_policy)
+        .description(s3::describe(&cli.grants))
+        .assume_role_policy_document(trust_policy.to_json())
         .send()
         .await?;
 
@@ -57,23 +76,11 @@ async fn main() -> anyhow::Result<()> {
     println!("Created role {}", role_name);
 
     // Attach a Policy to the Role
-    let policy = r#"{
-        "Version": "2012-10-17",
-        "Statement": [
-            {
-                "Sid": "AllowFullS3Access",
-                "Effect": "Allow",
-                "Action": ["s3:*"],
-                "Resource": ["*"]
-            }
-        ]
-    }"#;
-
     iam_client
         .put_role_policy()
         .role_name(role_name)
         .policy_name("S3AccessPolicy")
-        .policy_document(policy)
+        .policy_document(policy.to_json())
         .send()
         .await?;
     println!("Added policy to role: {}", role_name);
@@ -258,12 +265,12 @@ async fn main() -> anyhow::Result<()> {
 
     // It takes a while for changes to IAM (the Role, Policy, and Instance Profile)
     // to reach eventual consistency across all of the AWS Regions.  The proper solution
-    // is Waiters: https://github.com/awslabs/aws-sdk-rust/issues/400
-    let duration = 5;
-    println!("Sleeping for {duration} seconds to allow the IAM Instance Profile to propagate");
-    std::thread::sleep(std::time::Duration::from_secs(duration));
+    // is Waiters: https://github.com/awslabs/aws-sdk-rust/issues/400, until then poll
+    // IAM and retry EC2 until it can see the Instance Profile.
+    let deadline = Instant::now() + PROPAGATION_TIMEOUT;
+    wait_for_instance_profile(&iam_client, profile_name, PROPAGATION_TIMEOUT).await?;
 
-    let resp = ec2_client
+    let run_instances = ec2_client
         .run_instances()
         .instance_type(InstanceType::T3Micro)
         .image_id("ami-065793e81b1869261")
@@ -274,9 +281,30 @@ async fn main() -> anyhow::Result<()> {
         .key_name("rust-test")
         .instance_initiated_shutdown_behavior(ShutdownBehavior::Terminate)
         .iam_instance_profile(instance_profile)
-        .user_data(userdata)
-        .send()
-        .await?;
+        .user_data(userdata);
+
+    let mut backoff = Backoff::new();
+    let resp = loop {
+        match run_instances.clone().send().await {
+            Err(err) if is_profile_not_ready(&err) => {
+                if Instant::now() + backoff.delay() > deadline {
+                    anyhow::bail!(
+                        "EC2 could not use Instance Profile {} after {} seconds: {}",
+                        profile_name,
+                        PROPAGATION_TIMEOUT.as_secs(),
+                        DisplayErrorContext(&err)
+                    );
+                }
+                println!(
+                    "Instance Profile {} is not visible to EC2 yet, retrying in {} seconds",
+                    profile_name,
+                    backoff.delay().as_secs()
+                );
+                backoff.wait().await;
+            }
+            result => break result?,
+        }
+    };
 
     let instances: Vec<String> = resp
         .instances()
@@ -338,3 +366,11 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+// EC2 reports an Instance Profile it can't see yet as an invalid parameter,
+// which is also what it says for a profile that really doesn't exist.
+fn is_profile_not_ready<R>(err: &SdkError<RunInstancesError, R>) -> bool {
+    let message = err.message().unwrap_or_default().to_lowercase();
+    err.code() == Some("InvalidParameterValue")
+        && (message.contains("iaminstanceprofile") || message.contains("instance profile"))
+}
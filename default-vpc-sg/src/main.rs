@@ -1,8 +1,46 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
 use aws_config::meta::region::RegionProviderChain;
-use aws_sdk_ec2::{types::Filter, Client, Error};
+use aws_sdk_ec2::{
+    types::{Filter, ResourceType, Tag, TagSpecification},
+    Client, Error,
+};
+use clap::{Args, Parser, Subcommand};
+
+// Groups created here are tagged so that cleanup can find them.  Older runs
+// didn't tag, so untagged groups in the default VPC with exactly these names
+// are also treated as ours.
+const PROJECT_TAG: &str = "default-vpc-sg";
+const GROUP_NAMES: &[&str] = &["SG-One", "SG-Two"];
+
+#[derive(Parser)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create SG-One and SG-Two in the default VPC and trust SG-One from
+    /// SG-Two.  This is what happens without a command
+    Create,
+    /// Delete the security groups created by previous runs
+    Cleanup(CleanupArgs),
+}
+
+#[derive(Args)]
+struct CleanupArgs {
+    /// Show the rules and groups that would be deleted without deleting them
+    #[clap(long)]
+    dry_run: bool,
+    /// Don't ask for confirmation
+    #[clap(long)]
+    yes: bool,
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Error> {
+async fn main() -> anyhow::Result<()> {
     env_logger::init();
 
     let region_provider = RegionProviderChain::default_provider().or_else("eu-west-1");
@@ -10,6 +48,18 @@ async fn main() -> Result<(), Error> {
     let config = aws_config::from_env().region(region_provider).load().await;
     let client = Client::new(&config);
 
+    let cli = Cli::parse();
+    let vpcid = default_vpc(&client).await?;
+
+    match cli.command.unwrap_or(Command::Create) {
+        Command::Create => create_groups(&client, &vpcid).await?,
+        Command::Cleanup(args) => cleanup_groups(&client, &vpcid, &args).await?,
+    }
+
+    Ok(())
+}
+
+async fn default_vpc(client: &Client) -> Result<String, Error> {
     let filter = Filter::builder().name("is-default").values("true").build();
 
     let resp = client.describe_vpcs().filters(filter).send().await?;
@@ -23,12 +73,23 @@ async fn main() -> Result<(), Error> {
         .expect("should always get a VPC ID");
     println!("Got default VPC: {}", &vpcid);
 
+    Ok(vpcid.to_string())
+}
+
+async fn create_groups(client: &Client, vpcid: &str) -> Result<(), Error> {
+    let tag = Tag::builder().key("project").value(PROJECT_TAG).build();
+    let tag_spec = TagSpecification::builder()
+        .resource_type(ResourceType::SecurityGroup)
+        .tags(tag)
+        .build();
+
     // Create the first security group
     let resp = client
         .create_security_group()
         .group_name("SG-One")
         .description("First Security Group")
         .vpc_id(vpcid)
+        .tag_specifications(tag_spec.clone())
         .send()
         .await?;
 
@@ -69,6 +130,7 @@ async fn main() -> Result<(), Error> {
         .group_name("SG-Two")
         .description("Second Security Group")
         .vpc_id(vpcid)
+        .tag_specifications(tag_spec)
         .send()
         .await?;
 
@@ -115,3 +177,199 @@ async fn main() -> Result<(), Error> {
 
     Ok(())
 }
+
+// A rule in one of our groups that references another of our groups, and has
+// to go before the referenced group can be deleted.
+struct Revocation {
+    group_id: String,
+    rule_id: String,
+    egress: bool,
+}
+
+struct CleanupPlan {
+    revocations: Vec<Revocation>,
+    deletions: BTreeMap<String, String>,
+    // Groups we'd delete but that rules in someone else's groups reference,
+    // with those rules.  Leaving them is safer than editing other groups.
+    kept: BTreeMap<String, Vec<String>>,
+}
+
+async fn cleanup_groups(client: &Client, vpcid: &str, args: &CleanupArgs) -> anyhow::Result<()> {
+    let ours = find_groups(client, vpcid).await?;
+    if ours.is_empty() {
+        println!("No security groups to clean up in {}", vpcid);
+        return Ok(());
+    }
+
+    let plan = plan_cleanup(client, ours).await?;
+    for (group_id, rules) in &plan.kept {
+        println!(
+            "Keeping {}, it is referenced by {}",
+            group_id,
+            rules.join(", ")
+        );
+    }
+    for revocation in &plan.revocations {
+        println!(
+            "Will revoke {} from {}",
+            revocation.rule_id, revocation.group_id
+        );
+    }
+    for (group_id, name) in &plan.deletions {
+        println!("Will delete {} ({})", group_id, name);
+    }
+
+    if args.dry_run || plan.deletions.is_empty() {
+        return Ok(());
+    }
+    if !args.yes && !confirm()? {
+        println!("Nothing deleted.");
+        return Ok(());
+    }
+
+    for revocation in &plan.revocations {
+        if revocation.egress {
+            client
+                .revoke_security_group_egress()
+                .group_id(&revocation.group_id)
+                .security_group_rule_ids(&revocation.rule_id)
+                .send()
+                .await?;
+        } else {
+            client
+                .revoke_security_group_ingress()
+                .group_id(&revocation.group_id)
+                .security_group_rule_ids(&revocation.rule_id)
+                .send()
+                .await?;
+        }
+        println!(
+            "Revoked {} from {}",
+            revocation.rule_id, revocation.group_id
+        );
+    }
+
+    for group_id in plan.deletions.keys() {
+        client
+            .delete_security_group()
+            .group_id(group_id)
+            .send()
+            .await?;
+        println!("Deleted {}", group_id);
+    }
+
+    Ok(())
+}
+
+// Groups that reference each other can't be deleted until the rules doing the
+// referencing are gone.  Only rules in our own groups are revoked.
+async fn plan_cleanup(
+    client: &Client,
+    mut ours: BTreeMap<String, String>,
+) -> Result<CleanupPlan, Error> {
+    let mut internal = Vec::new();
+    let mut kept: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    let mut next_token = None;
+    loop {
+        let resp = client
+            .describe_security_group_rules()
+            .set_next_token(next_token)
+            .send()
+            .await?;
+
+        for rule in resp.security_group_rules().unwrap_or_default() {
+            let Some(referenced) = rule.referenced_group_info().and_then(|g| g.group_id()) else {
+                continue;
+            };
+            if !ours.contains_key(referenced) {
+                continue;
+            }
+
+            let group_id = rule.group_id().expect("a rule should always have a group");
+            let rule_id = rule
+                .security_group_rule_id()
+                .expect("a rule should always have an ID");
+            if !ours.contains_key(group_id) {
+                kept.entry(referenced.to_string())
+                    .or_default()
+                    .push(format!("{} in {}", rule_id, group_id));
+            } else if group_id != referenced {
+                internal.push((
+                    referenced.to_string(),
+                    Revocation {
+                        group_id: group_id.to_string(),
+                        rule_id: rule_id.to_string(),
+                        egress: rule.is_egress().unwrap_or_default(),
+                    },
+                ));
+            }
+        }
+
+        next_token = resp.next_token().map(str::to_string);
+        if next_token.is_none() {
+            break;
+        }
+    }
+
+    ours.retain(|id, _| !kept.contains_key(id));
+    let revocations = internal
+        .into_iter()
+        .filter(|(referenced, _)| ours.contains_key(referenced))
+        .map(|(_, revocation)| revocation)
+        .collect();
+
+    Ok(CleanupPlan {
+        revocations,
+        deletions: ours,
+        kept,
+    })
+}
+
+fn confirm() -> anyhow::Result<bool> {
+    print!("Revoke these rules and delete these groups? [y/N] ");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+async fn find_groups(client: &Client, vpcid: &str) -> Result<BTreeMap<String, String>, Error> {
+    let vpc_filter = Filter::builder().name("vpc-id").values(vpcid).build();
+
+    let mut groups = BTreeMap::new();
+    let mut next_token = None;
+    loop {
+        let resp = client
+            .describe_security_groups()
+            .filters(vpc_filter.clone())
+            .set_next_token(next_token)
+            .send()
+            .await?;
+
+        for sg in resp.security_groups().unwrap_or_default() {
+            let name = sg.group_name().unwrap_or_default();
+            let tagged = sg
+                .tags()
+                .unwrap_or_default()
+                .iter()
+                .any(|t| t.key() == Some("project") && t.value() == Some(PROJECT_TAG));
+
+            if tagged || GROUP_NAMES.contains(&name) {
+                let id = sg
+                    .group_id()
+                    .expect("should always get a security group ID");
+                println!("Found {} ({})", id, name);
+                groups.insert(id.to_string(), name.to_string());
+            }
+        }
+
+        next_token = resp.next_token().map(str::to_string);
+        if next_token.is_none() {
+            break;
+        }
+    }
+
+    Ok(groups)
+}
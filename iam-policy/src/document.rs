@@ -0,0 +1,297 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::Error;
+
+pub const VERSION: &str = "2012-10-17";
+
+// Operator, e.g. StringLike, to condition key, e.g. s3:prefix, to values.
+pub type Condition = BTreeMap<String, BTreeMap<String, Vec<String>>>;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PolicyDocument {
+    // Optional in the grammar, but without it IAM assumes the 2008 language.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub statement: Vec<Statement>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Principal {
+    // "*", anyone at all.
+    Any,
+    // Principal type, e.g. AWS or Service, to identifiers.
+    Typed(BTreeMap<String, Vec<String>>),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Statement {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    pub effect: Effect,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub principal: Option<Principal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_principal: Option<Principal>,
+    #[serde(
+        default,
+        deserialize_with = "strings",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub action: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "strings",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub not_action: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "strings",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub resource: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "strings",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub not_resource: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "condition",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub condition: Condition,
+}
+
+impl PolicyDocument {
+    pub fn new() -> Self {
+        Self {
+            version: Some(VERSION.to_string()),
+            id: None,
+            statement: Vec::new(),
+        }
+    }
+
+    // A trust policy letting an AWS service, e.g. ec2.amazonaws.com, assume a role.
+    pub fn assume_role_for_service(service: &str) -> Self {
+        Self::new().statement(
+            Statement::allow()
+                .sid("AssumeRole")
+                .principal("Service", service)
+                .action("sts:AssumeRole"),
+        )
+    }
+
+    pub fn statement(mut self, statement: Statement) -> Self {
+        self.statement.push(statement);
+        self
+    }
+
+    // Policy documents returned by the IAM API are URL encoded.
+    pub fn from_url_encoded(s: &str) -> Result<Self, Error> {
+        let decoded = urlencoding::decode(s)?;
+        decoded.parse()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a policy document should always serialise")
+    }
+}
+
+impl Default for PolicyDocument {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromStr for PolicyDocument {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+impl fmt::Display for PolicyDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_json())
+    }
+}
+
+impl Statement {
+    pub fn new(effect: Effect) -> Self {
+        Self {
+            sid: None,
+            effect,
+            principal: None,
+            not_principal: None,
+            action: Vec::new(),
+            not_action: Vec::new(),
+            resource: Vec::new(),
+            not_resource: Vec::new(),
+            condition: BTreeMap::new(),
+        }
+    }
+
+    pub fn allow() -> Self {
+        Self::new(Effect::Allow)
+    }
+
+    pub fn deny() -> Self {
+        Self::new(Effect::Deny)
+    }
+
+    pub fn sid(mut self, sid: impl Into<String>) -> Self {
+        self.sid = Some(sid.into());
+        self
+    }
+
+    pub fn principal(mut self, kind: &str, id: impl Into<String>) -> Self {
+        match &mut self.principal {
+            Some(Principal::Typed(principals)) => principals
+                .entry(kind.to_string())
+                .or_default()
+                .push(id.into()),
+            _ => {
+                let principals = BTreeMap::from([(kind.to_string(), vec![id.into()])]);
+                self.principal = Some(Principal::Typed(principals));
+            }
+        }
+        self
+    }
+
+    pub fn any_principal(mut self) -> Self {
+        self.principal = Some(Principal::Any);
+        self
+    }
+
+    pub fn action(mut self, action: impl Into<String>) -> Self {
+        self.action.push(action.into());
+        self
+    }
+
+    pub fn not_action(mut self, action: impl Into<String>) -> Self {
+        self.not_action.push(action.into());
+        self
+    }
+
+    pub fn resource(mut self, resource: impl Into<String>) -> Self {
+        self.resource.push(resource.into());
+        self
+    }
+
+    pub fn not_resource(mut self, resource: impl Into<String>) -> Self {
+        self.not_resource.push(resource.into());
+        self
+    }
+
+    pub fn condition(mut self, operator: &str, key: &str, value: impl Into<String>) -> Self {
+        self.condition
+            .entry(operator.to_string())
+            .or_default()
+            .entry(key.to_string())
+            .or_default()
+            .push(value.into());
+        self
+    }
+}
+
+impl Serialize for Principal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Principal::Any => serializer.serialize_str("*"),
+            Principal::Typed(principals) => principals.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Principal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::String(s) if s == "*" => Ok(Principal::Any),
+            Value::Object(map) => {
+                let mut principals = BTreeMap::new();
+                for (kind, ids) in map {
+                    principals.insert(kind, value_to_strings(ids).map_err(de::Error::custom)?);
+                }
+                Ok(Principal::Typed(principals))
+            }
+            other => Err(de::Error::custom(format!(
+                "expected \"*\" or a map of principals, got {}",
+                other
+            ))),
+        }
+    }
+}
+
+// The policy grammar allows a single value anywhere a list is expected.
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => Ok(vec![one]),
+        OneOrMany::Many(many) => Ok(many),
+    }
+}
+
+fn strings<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    value_to_strings(Value::deserialize(deserializer)?).map_err(de::Error::custom)
+}
+
+fn condition<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Condition, D::Error> {
+    let raw: BTreeMap<String, BTreeMap<String, Value>> = BTreeMap::deserialize(deserializer)?;
+
+    let mut condition = Condition::new();
+    for (operator, keys) in raw {
+        let mut values = BTreeMap::new();
+        for (key, value) in keys {
+            values.insert(key, value_to_strings(value).map_err(de::Error::custom)?);
+        }
+        condition.insert(operator, values);
+    }
+    Ok(condition)
+}
+
+// Condition values can be booleans or numbers as well as strings, IAM treats
+// them all as strings.
+fn value_to_strings(value: Value) -> Result<Vec<String>, String> {
+    let scalar = |value: Value| match value {
+        Value::String(s) => Ok(s),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        other => Err(format!("expected a string, got {}", other)),
+    };
+
+    match value {
+        Value::Array(values) => values.into_iter().map(scalar).collect(),
+        value => Ok(vec![scalar(value)?]),
+    }
+}
@@ -0,0 +1,15 @@
+pub mod document;
+pub mod s3;
+pub mod simulate;
+pub mod validate;
+
+pub use document::{Effect, PolicyDocument, Principal, Statement};
+pub use validate::{Issue, Level, PolicyKind};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("policy document is not valid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("policy document is not valid URL encoded UTF-8: {0}")]
+    Encoding(#[from] std::string::FromUtf8Error),
+}
@@ -0,0 +1,191 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::document::{PolicyDocument, Statement};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    List,
+    Read,
+    Write,
+}
+
+impl FromStr for Access {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "list" => Ok(Access::List),
+            "read" => Ok(Access::Read),
+            "write" => Ok(Access::Write),
+            other => Err(format!(
+                "unknown access level '{}', expected read, write or list",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::List => write!(f, "list"),
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+        }
+    }
+}
+
+// Access to a bucket, or to the keys under a prefix in a bucket, written on
+// the command line as BUCKET[/PREFIX][:ACCESS,...], e.g. "renders/frames:read,list".
+// Without any access levels the grant is read, write and list.
+#[derive(Clone, Debug)]
+pub struct S3Grant {
+    pub bucket: String,
+    pub prefix: Option<String>,
+    pub access: Vec<Access>,
+}
+
+impl FromStr for S3Grant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, access) = match s.split_once(':') {
+            Some((path, access)) => (path, access),
+            None => (s, "list,read,write"),
+        };
+
+        let (bucket, prefix) = match path.split_once('/') {
+            Some((bucket, prefix)) => (bucket, prefix.trim_matches('/')),
+            None => (path, ""),
+        };
+        validate_bucket_name(bucket)?;
+
+        let mut access = access
+            .split(',')
+            .map(Access::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        access.sort();
+        access.dedup();
+
+        Ok(S3Grant {
+            bucket: bucket.to_string(),
+            prefix: (!prefix.is_empty()).then(|| prefix.to_string()),
+            access,
+        })
+    }
+}
+
+// Written the same way it's parsed, e.g. "renders/frames:list,read".
+impl fmt::Display for S3Grant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.bucket)?;
+        if let Some(prefix) = &self.prefix {
+            write!(f, "/{}", prefix)?;
+        }
+        let access: Vec<String> = self.access.iter().map(Access::to_string).collect();
+        write!(f, ":{}", access.join(","))
+    }
+}
+
+impl S3Grant {
+    pub fn bucket_arn(&self) -> String {
+        format!("arn:aws:s3:::{}", self.bucket)
+    }
+
+    pub fn objects_arn(&self) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("arn:aws:s3:::{}/{}/*", self.bucket, prefix),
+            None => format!("arn:aws:s3:::{}/*", self.bucket),
+        }
+    }
+}
+
+// A Role description saying what the grants allow.  IAM limits descriptions
+// to 1000 characters.
+pub fn describe(grants: &[S3Grant]) -> String {
+    let grants: Vec<String> = grants.iter().map(S3Grant::to_string).collect();
+    let mut description = format!("S3 access to {}", grants.join(" "));
+    // Cut on a character boundary, a prefix can contain any UTF-8.
+    if let Some((end, _)) = description
+        .char_indices()
+        .nth(997)
+        .filter(|_| description.chars().count() > 1000)
+    {
+        description.truncate(end);
+        description.push_str("...");
+    }
+    description
+}
+
+// Listing is granted on the bucket and, when there's a prefix, restricted to
+// that prefix with a condition; reading and writing are granted on the keys.
+pub fn s3_policy(grants: &[S3Grant]) -> PolicyDocument {
+    let mut policy = PolicyDocument::new();
+
+    for (n, grant) in grants.iter().enumerate() {
+        let n = n + 1;
+        for access in &grant.access {
+            let statement = match access {
+                Access::List => {
+                    let statement = Statement::allow()
+                        .sid(format!("ListBucket{}", n))
+                        .action("s3:ListBucket")
+                        .resource(grant.bucket_arn());
+                    match &grant.prefix {
+                        Some(prefix) => statement
+                            .condition("StringLike", "s3:prefix", prefix.clone())
+                            .condition("StringLike", "s3:prefix", format!("{}/*", prefix)),
+                        None => statement,
+                    }
+                }
+                Access::Read => Statement::allow()
+                    .sid(format!("ReadObjects{}", n))
+                    .action("s3:GetObject")
+                    .resource(grant.objects_arn()),
+                Access::Write => Statement::allow()
+                    .sid(format!("WriteObjects{}", n))
+                    .action("s3:PutObject")
+                    .action("s3:AbortMultipartUpload")
+                    .resource(grant.objects_arn()),
+            };
+            policy = policy.statement(statement);
+        }
+    }
+
+    policy
+}
+
+fn validate_bucket_name(name: &str) -> Result<(), String> {
+    let valid_chars = name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.');
+    let valid_ends = name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.ends_with(|c: char| c.is_ascii_alphanumeric());
+
+    if !(3..=63).contains(&name.len()) || !valid_chars || !valid_ends {
+        return Err(format!("'{}' is not a valid S3 bucket name", name));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_descriptions_are_kept_whole() {
+        let grants = vec![S3Grant::from_str("renders/frames:read,list").unwrap()];
+        assert_eq!(describe(&grants), "S3 access to renders/frames:list,read");
+    }
+
+    #[test]
+    fn long_descriptions_are_cut_to_1000_characters() {
+        let prefix = "é".repeat(1200);
+        let grants = vec![S3Grant::from_str(&format!("renders/{}:read", prefix)).unwrap()];
+
+        let description = describe(&grants);
+        assert_eq!(description.chars().count(), 1000);
+        assert!(description.ends_with("é..."));
+    }
+}
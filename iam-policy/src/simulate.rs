@@ -0,0 +1,350 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::document::{Effect, PolicyDocument, Statement};
+
+// A much simplified version of IAM policy evaluation for identity policies:
+// an explicit Deny beats any Allow, and anything not allowed is denied.
+// Permission boundaries, SCPs, session policies and resource policies aren't
+// considered, and only the common condition operators are understood.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Allowed,
+    ExplicitDeny,
+    ImplicitDeny,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decision::Allowed => write!(f, "allowed"),
+            Decision::ExplicitDeny => write!(f, "explicitly denied"),
+            Decision::ImplicitDeny => write!(f, "implicitly denied"),
+        }
+    }
+}
+
+// The statement that decided the outcome.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Matched {
+    pub policy: String,
+    pub index: usize,
+    pub sid: Option<String>,
+}
+
+impl fmt::Display for Matched {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.sid {
+            Some(sid) => write!(f, "{} statement {} ({})", self.policy, self.index, sid),
+            None => write!(f, "{} statement {}", self.policy, self.index),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Evaluation {
+    pub decision: Decision,
+    pub matched: Option<Matched>,
+    // Conditions that couldn't be evaluated, and what was assumed instead.
+    pub notes: Vec<String>,
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.matched {
+            Some(matched) => write!(f, "{} by {}", self.decision, matched),
+            None => write!(f, "{}, no statement matched", self.decision),
+        }
+    }
+}
+
+pub struct Request<'a> {
+    pub action: &'a str,
+    pub resource: &'a str,
+    // Condition keys, e.g. s3:prefix, and their values for this request.
+    pub context: BTreeMap<String, Vec<String>>,
+}
+
+impl<'a> Request<'a> {
+    pub fn new(action: &'a str, resource: &'a str) -> Self {
+        Self {
+            action,
+            resource,
+            context: BTreeMap::new(),
+        }
+    }
+
+    pub fn context(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.context
+            .entry(key.to_string())
+            .or_default()
+            .push(value.into());
+        self
+    }
+}
+
+// Evaluate a request against named policies, e.g. those attached to a role.
+pub fn evaluate(policies: &[(String, PolicyDocument)], request: &Request) -> Evaluation {
+    let mut allowed = None;
+    let mut notes = Vec::new();
+
+    for (name, policy) in policies {
+        for (index, statement) in policy.statement.iter().enumerate() {
+            if !statement_matches(statement, request, &mut notes) {
+                continue;
+            }
+
+            let matched = Matched {
+                policy: name.clone(),
+                index,
+                sid: statement.sid.clone(),
+            };
+            match statement.effect {
+                Effect::Deny => {
+                    return Evaluation {
+                        decision: Decision::ExplicitDeny,
+                        matched: Some(matched),
+                        notes,
+                    }
+                }
+                Effect::Allow => {
+                    allowed.get_or_insert(matched);
+                }
+            }
+        }
+    }
+
+    Evaluation {
+        decision: if allowed.is_some() {
+            Decision::Allowed
+        } else {
+            Decision::ImplicitDeny
+        },
+        matched: allowed,
+        notes,
+    }
+}
+
+fn statement_matches(statement: &Statement, request: &Request, notes: &mut Vec<String>) -> bool {
+    let action =
+        |pattern: &String| wildcard_match(&pattern.to_lowercase(), &request.action.to_lowercase());
+    // A statement with neither Action nor NotAction isn't valid, so it
+    // applies to nothing rather than to everything.
+    let action_matches = if !statement.action.is_empty() {
+        statement.action.iter().any(action)
+    } else if !statement.not_action.is_empty() {
+        !statement.not_action.iter().any(action)
+    } else {
+        false
+    };
+
+    let resource = |pattern: &String| wildcard_match(pattern, request.resource);
+    let resource_matches = if !statement.resource.is_empty() {
+        statement.resource.iter().any(resource)
+    } else if !statement.not_resource.is_empty() {
+        !statement.not_resource.iter().any(resource)
+    } else {
+        true
+    };
+
+    if !(action_matches && resource_matches) {
+        return false;
+    }
+
+    for (operator, keys) in &statement.condition {
+        for (key, values) in keys {
+            match condition_matches(operator, key, values, request) {
+                Ok(true) => {}
+                Ok(false) => return false,
+                // Err on the side of denying: an Allow that can't be checked
+                // doesn't allow, and a Deny that can't be checked does deny.
+                Err(note) => {
+                    let sid = statement.sid.as_deref().unwrap_or("statement");
+                    let assumed = match statement.effect {
+                        Effect::Allow => "assumed not to match",
+                        Effect::Deny => "assumed to match",
+                    };
+                    notes.push(format!("{}: {}, {}", sid, note, assumed));
+                    if statement.effect == Effect::Allow {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    true
+}
+
+fn condition_matches(
+    operator: &str,
+    key: &str,
+    values: &[String],
+    request: &Request,
+) -> Result<bool, String> {
+    let (operator, if_exists) = match operator.strip_suffix("IfExists") {
+        Some(operator) => (operator, true),
+        None => (operator, false),
+    };
+
+    if operator == "Null" {
+        let absent = !request.context.contains_key(key);
+        return Ok(values
+            .iter()
+            .any(|v| v.eq_ignore_ascii_case("true") == absent));
+    }
+
+    let (compare, negated): (fn(&str, &str) -> bool, bool) = match operator {
+        "StringEquals" | "ArnEquals" => (|v, a| v == a, false),
+        "StringNotEquals" | "ArnNotEquals" => (|v, a| v == a, true),
+        "StringEqualsIgnoreCase" | "Bool" => (|v, a| v.eq_ignore_ascii_case(a), false),
+        "StringNotEqualsIgnoreCase" => (|v, a| v.eq_ignore_ascii_case(a), true),
+        "StringLike" | "ArnLike" => (wildcard_match, false),
+        "StringNotLike" | "ArnNotLike" => (wildcard_match, true),
+        other => return Err(format!("condition operator {} is not supported", other)),
+    };
+
+    let Some(actual) = request.context.get(key) else {
+        // Without the key only IfExists and negated operators can match.
+        return Ok(if_exists || negated);
+    };
+
+    let any = actual
+        .iter()
+        .any(|a| values.iter().any(|v| compare(v.as_str(), a.as_str())));
+    Ok(any != negated)
+}
+
+// IAM's wildcards: * matches any run of characters, ? matches exactly one.
+pub fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+
+    let (mut p, mut v) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while v < value.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == value[v]) {
+            p += 1;
+            v += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, v));
+            p += 1;
+        } else if let Some((star_p, star_v)) = star {
+            // Let the last * swallow one more character and try again.
+            p = star_p + 1;
+            v = star_v + 1;
+            star = Some((star_p, star_v + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(statements: Vec<Statement>) -> Vec<(String, PolicyDocument)> {
+        let document = statements
+            .into_iter()
+            .fold(PolicyDocument::new(), PolicyDocument::statement);
+        vec![("test".to_string(), document)]
+    }
+
+    #[test]
+    fn star_matches_any_run_of_characters() {
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match(
+            "arn:aws:s3:::bucket/*",
+            "arn:aws:s3:::bucket/a/b"
+        ));
+        assert!(wildcard_match("a*b*c", "aXXbYYc"));
+        assert!(!wildcard_match(
+            "arn:aws:s3:::bucket/*",
+            "arn:aws:s3:::other/a"
+        ));
+    }
+
+    #[test]
+    fn question_mark_matches_exactly_one_character() {
+        assert!(wildcard_match("file?.png", "file1.png"));
+        assert!(!wildcard_match("file?.png", "file.png"));
+        assert!(!wildcard_match("file?.png", "file12.png"));
+    }
+
+    #[test]
+    fn actions_match_ignoring_case() {
+        let policies = policy(vec![Statement::allow().action("s3:Get*").resource("*")]);
+
+        let request = Request::new("S3:getobject", "arn:aws:s3:::bucket/key");
+        assert_eq!(evaluate(&policies, &request).decision, Decision::Allowed);
+    }
+
+    #[test]
+    fn explicit_deny_beats_allow() {
+        let policies = policy(vec![
+            Statement::allow().action("s3:*").resource("*"),
+            Statement::deny()
+                .sid("NoDeletes")
+                .action("s3:DeleteObject")
+                .resource("*"),
+        ]);
+
+        let request = Request::new("s3:DeleteObject", "arn:aws:s3:::bucket/key");
+        let evaluation = evaluate(&policies, &request);
+        assert_eq!(evaluation.decision, Decision::ExplicitDeny);
+        assert_eq!(
+            evaluation.matched.unwrap().sid.as_deref(),
+            Some("NoDeletes")
+        );
+
+        let request = Request::new("s3:GetObject", "arn:aws:s3:::bucket/key");
+        assert_eq!(evaluate(&policies, &request).decision, Decision::Allowed);
+    }
+
+    #[test]
+    fn deny_with_unsupported_condition_still_denies() {
+        let policies = policy(vec![
+            Statement::allow().action("s3:*").resource("*"),
+            Statement::deny().action("s3:*").resource("*").condition(
+                "DateGreaterThan",
+                "aws:CurrentTime",
+                "2020-01-01T00:00:00Z",
+            ),
+        ]);
+
+        let request = Request::new("s3:GetObject", "arn:aws:s3:::bucket/key");
+        let evaluation = evaluate(&policies, &request);
+        assert_eq!(evaluation.decision, Decision::ExplicitDeny);
+        assert_eq!(evaluation.notes.len(), 1);
+    }
+
+    #[test]
+    fn allow_with_unsupported_condition_does_not_allow() {
+        let policies = policy(vec![Statement::allow()
+            .action("s3:*")
+            .resource("*")
+            .condition("DateGreaterThan", "aws:CurrentTime", "2020-01-01T00:00:00Z")]);
+
+        let request = Request::new("s3:GetObject", "arn:aws:s3:::bucket/key");
+        assert_eq!(
+            evaluate(&policies, &request).decision,
+            Decision::ImplicitDeny
+        );
+    }
+
+    #[test]
+    fn statement_without_actions_matches_nothing() {
+        let policies = policy(vec![Statement::allow().resource("*")]);
+
+        let request = Request::new("s3:GetObject", "arn:aws:s3:::bucket/key");
+        assert_eq!(
+            evaluate(&policies, &request).decision,
+            Decision::ImplicitDeny
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::document::{Effect, PolicyDocument, Principal, Statement, VERSION};
+
+// Where a policy is used changes which elements it must and mustn't have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolicyKind {
+    // Attached to a user, group or role: no Principal, needs a Resource.
+    Identity,
+    // A role's trust policy: needs a Principal, no Resource.
+    Trust,
+    // Attached to a resource such as a bucket or queue: needs both.
+    Resource,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Issue {
+    pub level: Level,
+    // Index of the statement in the document, None for the document itself.
+    pub statement: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.level {
+            Level::Warning => "warning",
+            Level::Error => "error",
+        };
+        match self.statement {
+            Some(n) => write!(f, "{}: statement {}: {}", level, n, self.message),
+            None => write!(f, "{}: {}", level, self.message),
+        }
+    }
+}
+
+impl PolicyDocument {
+    pub fn validate(&self, kind: PolicyKind) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let mut issue = |level, statement, message: String| {
+            issues.push(Issue {
+                level,
+                statement,
+                message,
+            })
+        };
+
+        match self.version.as_deref() {
+            None => issue(
+                Level::Error,
+                None,
+                format!("Version is missing, it should be \"{}\"", VERSION),
+            ),
+            Some(VERSION) => {}
+            Some("2008-10-17") => issue(
+                Level::Warning,
+                None,
+                format!(
+                    "Version 2008-10-17 doesn't support policy variables, use \"{}\"",
+                    VERSION
+                ),
+            ),
+            Some(other) => issue(Level::Error, None, format!("unknown Version \"{}\"", other)),
+        }
+
+        if self.statement.is_empty() {
+            issue(Level::Error, None, "there are no statements".to_string());
+        }
+
+        let mut sids = HashSet::new();
+        for (n, statement) in self.statement.iter().enumerate() {
+            if let Some(sid) = &statement.sid {
+                if !sid.chars().all(|c| c.is_ascii_alphanumeric()) {
+                    issue(
+                        Level::Error,
+                        Some(n),
+                        format!("Sid \"{}\" must only contain letters and digits", sid),
+                    );
+                }
+                if !sids.insert(sid) {
+                    issue(
+                        Level::Error,
+                        Some(n),
+                        format!("Sid \"{}\" is not unique", sid),
+                    );
+                }
+            }
+
+            for (level, message) in validate_statement(statement, kind) {
+                issue(level, Some(n), message);
+            }
+        }
+
+        issues
+    }
+
+    pub fn has_errors(&self, kind: PolicyKind) -> bool {
+        self.validate(kind).iter().any(|i| i.level == Level::Error)
+    }
+}
+
+fn validate_statement(statement: &Statement, kind: PolicyKind) -> Vec<(Level, String)> {
+    let mut issues = Vec::new();
+
+    match (statement.action.is_empty(), statement.not_action.is_empty()) {
+        (true, true) => issues.push((Level::Error, "needs an Action or NotAction".to_string())),
+        (false, false) => issues.push((
+            Level::Error,
+            "can't have both Action and NotAction".to_string(),
+        )),
+        _ => {}
+    }
+    for action in statement.action.iter().chain(&statement.not_action) {
+        if !valid_action(action) {
+            issues.push((
+                Level::Error,
+                format!(
+                    "\"{}\" is not a valid action, expected service:Action",
+                    action
+                ),
+            ));
+        }
+    }
+
+    let has_resource = !statement.resource.is_empty() || !statement.not_resource.is_empty();
+    if !statement.resource.is_empty() && !statement.not_resource.is_empty() {
+        issues.push((
+            Level::Error,
+            "can't have both Resource and NotResource".to_string(),
+        ));
+    }
+    match kind {
+        PolicyKind::Trust if has_resource => issues.push((
+            Level::Error,
+            "a trust policy can't have a Resource".to_string(),
+        )),
+        PolicyKind::Identity | PolicyKind::Resource if !has_resource => {
+            issues.push((Level::Error, "needs a Resource or NotResource".to_string()))
+        }
+        _ => {}
+    }
+    for resource in statement.resource.iter().chain(&statement.not_resource) {
+        if !valid_arn(resource) {
+            issues.push((Level::Error, format!("\"{}\" is not a valid ARN", resource)));
+        }
+    }
+
+    let principal = statement
+        .principal
+        .as_ref()
+        .or(statement.not_principal.as_ref());
+    match (kind, principal) {
+        (PolicyKind::Identity, Some(_)) => issues.push((
+            Level::Error,
+            "an identity policy can't have a Principal".to_string(),
+        )),
+        (PolicyKind::Trust | PolicyKind::Resource, None) => issues.push((
+            Level::Error,
+            "needs a Principal or NotPrincipal".to_string(),
+        )),
+        _ => {}
+    }
+    if let Some(principal) = &statement.principal {
+        issues.extend(validate_principal(statement, principal));
+    }
+
+    let all_actions = statement.action.iter().any(|a| a == "*");
+    let all_resources = statement.resource.iter().any(|r| r == "*");
+    if statement.effect == Effect::Allow && all_actions && all_resources {
+        issues.push((
+            Level::Warning,
+            "allows every action on every resource".to_string(),
+        ));
+    }
+
+    issues
+}
+
+fn validate_principal(statement: &Statement, principal: &Principal) -> Vec<(Level, String)> {
+    let mut issues = Vec::new();
+
+    let wildcard = match principal {
+        Principal::Any => true,
+        Principal::Typed(principals) => principals
+            .get("AWS")
+            .is_some_and(|ids| ids.iter().any(|id| id == "*")),
+    };
+    if wildcard && statement.effect == Effect::Allow {
+        // Conditions such as aws:SourceArn or aws:PrincipalOrgID can make a
+        // wildcard reasonable, without any it's open to the world.
+        if statement.condition.is_empty() {
+            issues.push((
+                Level::Error,
+                "allows any principal without a Condition".to_string(),
+            ));
+        } else {
+            issues.push((Level::Warning, "allows any principal".to_string()));
+        }
+    }
+
+    if let Principal::Typed(principals) = principal {
+        for (kind, ids) in principals {
+            match kind.as_str() {
+                "AWS" => {
+                    for id in ids {
+                        let account = id.len() == 12 && id.chars().all(|c| c.is_ascii_digit());
+                        if !(id == "*" || account || valid_arn(id)) {
+                            issues.push((
+                                Level::Error,
+                                format!("\"{}\" is not an account ID or ARN", id),
+                            ));
+                        }
+                    }
+                }
+                "Service" => {
+                    for id in ids {
+                        if !id.ends_with(".amazonaws.com") {
+                            issues.push((
+                                Level::Warning,
+                                format!("\"{}\" doesn't look like a service principal", id),
+                            ));
+                        }
+                    }
+                }
+                "Federated" | "CanonicalUser" => {}
+                other => issues.push((
+                    Level::Error,
+                    format!("unknown principal type \"{}\"", other),
+                )),
+            }
+        }
+    }
+
+    issues
+}
+
+fn valid_action(action: &str) -> bool {
+    if action == "*" {
+        return true;
+    }
+
+    match action.split_once(':') {
+        Some((service, name)) => {
+            !service.is_empty()
+                && service
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+                && !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '*' || c == '?')
+        }
+        None => false,
+    }
+}
+
+// arn:partition:service:region:account-id:resource, where region and account
+// are empty for global services such as S3 and IAM.
+pub fn valid_arn(arn: &str) -> bool {
+    if arn == "*" {
+        return true;
+    }
+
+    let parts: Vec<&str> = arn.splitn(6, ':').collect();
+    parts.len() == 6
+        && parts[0] == "arn"
+        && matches!(parts[1], "aws" | "aws-cn" | "aws-us-gov" | "*")
+        && !parts[2].is_empty()
+        && !parts[5].is_empty()
+}
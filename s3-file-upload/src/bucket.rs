@@ -0,0 +1,21 @@
+use aws_common::s3;
+use aws_common::{none_if, ErrorKind};
+use aws_sdk_s3::Client;
+
+// The region a bucket is in, or None if it doesn't exist.
+pub async fn bucket_region(client: &Client, bucket: &str) -> anyhow::Result<Option<String>> {
+    let result = s3::bucket_region(client, bucket).await;
+    Ok(none_if(result, ErrorKind::NotFound)?)
+}
+
+pub async fn create_bucket(client: &Client, bucket: &str) -> anyhow::Result<()> {
+    let region = s3::create_bucket(client, bucket).await?;
+    println!("Created bucket {} in {}", bucket, region);
+    Ok(())
+}
+
+pub async fn delete_bucket(client: &Client, bucket: &str) -> anyhow::Result<()> {
+    client.delete_bucket().bucket(bucket).send().await?;
+    println!("Deleted bucket {}", bucket);
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use std::path::{Path, PathBuf};
+
+use aws_common::Backoff;
+use aws_sdk_s3::error::DisplayErrorContext;
+use aws_sdk_s3::types::{ChecksumMode, ServerSideEncryption};
+use aws_sdk_s3::Client;
+use futures::{StreamExt, TryStreamExt};
+use indicatif::ProgressBar;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use uuid::Uuid;
+
+use crate::transfer::{e_tag_parts, file_e_tag, progress_bar, TransferOptions};
+
+// Inclusive byte range of the object, None for all of it.
+type Range = Option<(u64, u64)>;
+
+// Download an object to a temporary file next to the path, check it against
+// the ETag, and only then rename it into place so that the path is never left
+// holding part of a file.
+pub async fn download_file(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    path: &Path,
+    options: &TransferOptions,
+) -> anyhow::Result<()> {
+    let head = client.head_object().bucket(bucket).key(key).send().await?;
+    let size = head.content_length() as u64;
+    let e_tag = head.e_tag().map(str::to_string);
+    let encrypted_with_kms = matches!(
+        head.server_side_encryption(),
+        Some(ServerSideEncryption::AwsKms | ServerSideEncryption::AwsKmsDsse)
+    );
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let temp = temp_path(path);
+    let progress = progress_bar(key, size);
+
+    let result = async {
+        let file = tokio::fs::File::create(&temp).await?;
+        file.set_len(size).await?;
+        drop(file);
+
+        // The arguments only allow part sizes S3 accepts.
+        debug_assert!(options.part_size > 0);
+        let ranges: Vec<Range> = if size <= options.part_size {
+            vec![None]
+        } else {
+            (0..size.div_ceil(options.part_size))
+                .map(|n| {
+                    let start = n * options.part_size;
+                    Some((start, (start + options.part_size).min(size) - 1))
+                })
+                .collect()
+        };
+        futures::stream::iter(ranges)
+            .map(|range| {
+                download_range(
+                    client,
+                    bucket,
+                    key,
+                    e_tag.as_deref(),
+                    &temp,
+                    range,
+                    options,
+                    &progress,
+                )
+            })
+            .buffer_unordered(options.concurrency.max(1))
+            .try_collect::<Vec<()>>()
+            .await?;
+
+        match &e_tag {
+            Some(_) if encrypted_with_kms => {
+                progress.println(format!(
+                    "Not verifying {}, the ETag of an SSE-KMS object isn't an MD5",
+                    key
+                ));
+            }
+            // A correct download is kept even when it can't be verified.
+            Some(e_tag) if !verify(client, bucket, key, &temp, size, e_tag).await? => {
+                progress.println(format!(
+                    "Not verifying {}, its ETag can't be worked out locally, its parts may not all be the same size",
+                    key
+                ));
+            }
+            _ => {}
+        }
+
+        tokio::fs::rename(&temp, path).await?;
+        Ok(())
+    }
+    .await;
+
+    if result.is_err() {
+        progress.abandon();
+        let _ = tokio::fs::remove_file(&temp).await;
+    } else {
+        progress.finish();
+    }
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn download_range(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    e_tag: Option<&str>,
+    path: &Path,
+    range: Range,
+    options: &TransferOptions,
+    progress: &ProgressBar,
+) -> anyhow::Result<()> {
+    let mut backoff = Backoff::new();
+    let mut attempt = 0;
+    loop {
+        let mut written = 0;
+        match fetch_range(
+            client,
+            bucket,
+            key,
+            e_tag,
+            path,
+            range,
+            progress,
+            &mut written,
+        )
+        .await
+        {
+            Ok(()) => return Ok(()),
+            Err(err) if attempt < options.retries => {
+                attempt += 1;
+                progress.set_position(progress.position() - written);
+                progress.println(format!(
+                    "Download of {} failed, retrying in {} seconds: {}",
+                    range_label(range),
+                    backoff.delay().as_secs(),
+                    err
+                ));
+                backoff.wait().await;
+            }
+            Err(err) => {
+                return Err(err.context(format!(
+                    "download of {} failed after {} attempts",
+                    range_label(range),
+                    attempt + 1
+                )))
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn fetch_range(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    e_tag: Option<&str>,
+    path: &Path,
+    range: Range,
+    progress: &ProgressBar,
+    written: &mut u64,
+) -> anyhow::Result<()> {
+    // If-Match stops the ranges coming from different versions of an object
+    // that is overwritten part way through.  The SDK checks the whole object
+    // against any checksum stored with it, it can't for a range.
+    let checksum_mode = range.is_none().then_some(ChecksumMode::Enabled);
+    let resp = client
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .set_if_match(e_tag.map(str::to_string))
+        .set_range(range.map(|(start, end)| format!("bytes={}-{}", start, end)))
+        .set_checksum_mode(checksum_mode)
+        .send()
+        .await
+        .map_err(|err| anyhow::anyhow!("{}", DisplayErrorContext(&err)))?;
+
+    let mut file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+    if let Some((start, _)) = range {
+        file.seek(std::io::SeekFrom::Start(start)).await?;
+    }
+
+    let mut body = resp.body;
+    while let Some(bytes) = body.try_next().await? {
+        file.write_all(&bytes).await?;
+        *written += bytes.len() as u64;
+        progress.inc(bytes.len() as u64);
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+// Compare the downloaded file with the object's ETag.  For a multipart upload
+// that needs the size of the parts, which is the size of the first one, and
+// can only be done when every part but the last is that size.  Returns false
+// when the ETag can't be worked out locally, in which case If-Match on each
+// range is all that keeps the download consistent.
+async fn verify(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    path: &Path,
+    size: u64,
+    e_tag: &str,
+) -> anyhow::Result<bool> {
+    let e_tag = e_tag.trim_matches('"');
+    let Some(parts) = e_tag_parts(e_tag) else {
+        return Ok(false);
+    };
+
+    let part_size = match parts {
+        Some(parts) => {
+            let first = part_length(client, bucket, key, 1).await?;
+            // Uploaders are free to use parts of different sizes.
+            let last = part_length(client, bucket, key, parts).await?;
+            if first == 0 || (parts - 1) * first + last != size {
+                return Ok(false);
+            }
+            first
+        }
+        None => size,
+    };
+
+    match file_e_tag(path, size, part_size.max(1), parts).await? {
+        Some(local) if local == e_tag => Ok(true),
+        Some(local) => anyhow::bail!(
+            "{} is corrupt, its ETag should be {} but the download's is {}",
+            key,
+            e_tag,
+            local
+        ),
+        None => Ok(false),
+    }
+}
+
+async fn part_length(client: &Client, bucket: &str, key: &str, part: u64) -> anyhow::Result<u64> {
+    let head = client
+        .head_object()
+        .bucket(bucket)
+        .key(key)
+        .part_number(part as i32)
+        .send()
+        .await?;
+    Ok(head.content_length() as u64)
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .expect("Path should always have a final component")
+        .to_string_lossy();
+    path.with_file_name(format!(".{}.{}.part", name, Uuid::new_v4().simple()))
+}
+
+fn range_label(range: Range) -> String {
+    match range {
+        Some((start, end)) => format!("bytes {}-{}", start, end),
+        None => "the object".to_string(),
+    }
+}
@@ -1,76 +1,408 @@
-use std::path::Path;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
 
+use aws_common::s3;
 use aws_config::meta::region::RegionProviderChain;
-use aws_sdk_s3::primitives::ByteStream;
-use aws_sdk_s3::types::{BucketLocationConstraint, CreateBucketConfiguration};
-use aws_sdk_s3::{Client, Error};
+use aws_sdk_s3::types::StorageClass;
+use aws_sdk_s3::Client;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 use uuid::Uuid;
 
+use object::{Encryption, ObjectOptions};
+use presign::Method;
+use server::ServerOptions;
+use sync::{Location, S3Url, SyncOptions};
+use transfer::{TransferOptions, MIB};
+
+mod bucket;
+mod download;
+mod object;
+mod presign;
+mod server;
+mod sync;
+mod transfer;
+mod upload;
+
 const PREFIX: &str = "test";
 
-#[tokio::main]
-async fn main() -> Result<(), Error> {
-    let file = match std::env::args().nth(1) {
-        Some(file) => file,
-        _ => {
-            println!("Usage: s3-file-upload <FILENAME>");
-            std::process::exit(1);
+// The server's JWT signing secret comes from the environment rather than the
+// command line, where other users could see it.
+const JWT_SECRET_VAR: &str = "PRESIGN_JWT_SECRET";
+
+#[derive(Parser)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Upload a file to a bucket, creating the bucket if needed
+    Put(PutArgs),
+    /// Download an object
+    Get(GetArgs),
+    /// Copy the files that are missing or changed from a directory to an S3
+    /// prefix, or from an S3 prefix to a directory
+    Sync(SyncArgs),
+    /// Print a presigned URL to download or upload an object without AWS
+    /// credentials
+    Presign(PresignArgs),
+    /// Serve presigned URLs for a bucket to callers with a valid JWT
+    Serve(ServeArgs),
+}
+
+#[derive(Args)]
+struct PutArgs {
+    /// File to upload
+    file: PathBuf,
+    /// Bucket to upload to, it's created if it doesn't exist.  Without this
+    /// a new bucket with a random name is created
+    #[clap(long)]
+    bucket: Option<String>,
+    /// Prefix for the key of the uploaded file
+    #[clap(long, default_value = PREFIX)]
+    prefix: String,
+    /// Delete the file, and the bucket if it was created, once it's uploaded
+    #[clap(long)]
+    cleanup: bool,
+    #[clap(flatten)]
+    transfer: TransferArgs,
+    #[clap(flatten)]
+    object: ObjectArgs,
+}
+
+#[derive(Args)]
+struct GetArgs {
+    /// Object to download as s3://BUCKET/KEY
+    source: S3Url,
+    /// File or directory to download to, defaults to the current directory
+    destination: Option<PathBuf>,
+    #[clap(flatten)]
+    transfer: TransferArgs,
+}
+
+#[derive(Args)]
+struct SyncArgs {
+    /// Directory, or s3://BUCKET/PREFIX, to copy from
+    source: Location,
+    /// s3://BUCKET/PREFIX, or directory, to copy to
+    destination: Location,
+    /// Only sync paths matching this glob, e.g. "**/*.png"
+    #[clap(long)]
+    include: Vec<String>,
+    /// Don't sync paths matching this glob, e.g. "tmp/**"
+    #[clap(long)]
+    exclude: Vec<String>,
+    /// Delete files or objects at the destination that aren't at the source
+    #[clap(long)]
+    delete: bool,
+    /// Show what would be copied and deleted without changing anything
+    #[clap(long)]
+    dry_run: bool,
+    #[clap(flatten)]
+    transfer: TransferArgs,
+    /// Used for uploads, ignored when syncing from S3
+    #[clap(flatten)]
+    object: ObjectArgs,
+}
+
+#[derive(Args)]
+struct PresignArgs {
+    #[clap(value_enum)]
+    method: Method,
+    /// Object as s3://BUCKET/KEY
+    object: S3Url,
+    /// How long the URL works for, at most 7 days
+    #[clap(long, default_value = "1h", value_parser = humantime::parse_duration)]
+    expires_in: Duration,
+    /// For put, the content type the upload must use.  For get, the content
+    /// type S3 should send back
+    #[clap(long)]
+    content_type: Option<String>,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// Bucket, and optionally prefix, to hand out URLs for as s3://BUCKET/PREFIX
+    location: S3Url,
+    #[clap(long, default_value = "127.0.0.1:3000")]
+    listen: SocketAddr,
+    /// How long URLs work for when the caller doesn't say
+    #[clap(long, default_value = "15m", value_parser = humantime::parse_duration)]
+    expires_in: Duration,
+    /// The longest a caller can ask for a URL to work for
+    #[clap(long, default_value = "1h", value_parser = humantime::parse_duration)]
+    max_expires_in: Duration,
+    /// Content type uploads may use, can be repeated.  Any content type is
+    /// allowed without this
+    #[clap(long)]
+    content_type: Vec<String>,
+}
+
+#[derive(Args)]
+struct TransferArgs {
+    /// Size of each part in MiB, smaller files are transferred in one request.
+    /// S3 accepts parts from 5 MiB to 5 GiB
+    #[clap(long, default_value_t = 8, value_parser = clap::value_parser!(u64).range(5..=5120))]
+    part_size: u64,
+    /// Number of parts to transfer at the same time
+    #[clap(long, default_value_t = 4)]
+    concurrency: usize,
+    /// Number of times to retry a part that fails to transfer
+    #[clap(long, default_value_t = 3)]
+    retries: u32,
+}
+
+impl TransferArgs {
+    fn options(&self) -> TransferOptions {
+        TransferOptions {
+            part_size: self.part_size * MIB,
+            concurrency: self.concurrency,
+            retries: self.retries,
         }
-    };
-    let path = Path::new(&file);
-    let key = path
-        .file_name()
-        .expect("Path should always have a final component")
-        .to_string_lossy();
+    }
+}
+
+#[derive(Args)]
+struct ObjectArgs {
+    /// Server side encryption for uploaded objects
+    #[clap(long, value_enum)]
+    sse: Option<Sse>,
+    /// KMS key ID or ARN for SSE-KMS, implies --sse kms
+    #[clap(long)]
+    sse_kms_key_id: Option<String>,
+    /// Have S3 store and check a SHA-256 checksum of each object
+    #[clap(long)]
+    checksum_sha256: bool,
+    /// Content type, guessed from the file's extension if not given
+    #[clap(long)]
+    content_type: Option<String>,
+    /// User metadata as NAME=VALUE
+    #[clap(long, value_parser = parse_key_value)]
+    metadata: Vec<(String, String)>,
+    /// Storage class, e.g. STANDARD_IA or INTELLIGENT_TIERING
+    #[clap(long)]
+    storage_class: Option<String>,
+    /// Object tag as KEY=VALUE
+    #[clap(long, value_parser = parse_key_value)]
+    tag: Vec<(String, String)>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Sse {
+    /// SSE-S3, keys managed by S3
+    S3,
+    /// SSE-KMS, keys managed by KMS
+    Kms,
+}
+
+impl ObjectArgs {
+    fn options(&self) -> anyhow::Result<ObjectOptions> {
+        let encryption = match (self.sse, &self.sse_kms_key_id) {
+            (Some(Sse::S3), None) => Some(Encryption::S3),
+            (Some(Sse::S3), Some(_)) => anyhow::bail!("--sse-kms-key-id needs --sse kms"),
+            (Some(Sse::Kms), key_id) | (None, key_id @ Some(_)) => {
+                Some(Encryption::Kms(key_id.clone()))
+            }
+            (None, None) => None,
+        };
+
+        Ok(ObjectOptions {
+            encryption,
+            checksum: self.checksum_sha256,
+            content_type: self.content_type.clone(),
+            metadata: self.metadata.iter().cloned().collect(),
+            storage_class: self.storage_class.as_deref().map(StorageClass::from),
+            tags: self.tag.clone(),
+        })
+    }
+}
+
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("'{}' should be KEY=VALUE", s)),
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let cli = Cli::parse();
 
     let region_provider = RegionProviderChain::default_provider().or_else("eu-west-1");
 
     let config = aws_config::from_env().region(region_provider).load().await;
     let client = Client::new(&config);
 
-    let bucket_name = Uuid::new_v4();
+    match cli.command {
+        Command::Put(args) => put(&client, &args).await?,
+        Command::Get(args) => get(&client, &args).await?,
+        Command::Sync(args) => {
+            let options = SyncOptions {
+                include: args.include,
+                exclude: args.exclude,
+                delete: args.delete,
+                dry_run: args.dry_run,
+            };
+            let transfer = args.transfer.options();
+            match (&args.source, &args.destination) {
+                (Location::Local(source), Location::S3(destination)) => {
+                    let client = s3::client_for_bucket(&client, &destination.bucket).await?;
+                    let object = args.object.options()?;
+                    sync::sync_to_s3(&client, source, destination, &options, &transfer, &object)
+                        .await?
+                }
+                (Location::S3(source), Location::Local(destination)) => {
+                    let client = s3::client_for_bucket(&client, &source.bucket).await?;
+                    sync::sync_from_s3(&client, source, destination, &options, &transfer).await?
+                }
+                _ => anyhow::bail!("sync needs one directory and one s3:// location"),
+            }
+        }
+        Command::Presign(args) => presign(&client, &args).await?,
+        Command::Serve(args) => {
+            let secret = std::env::var(JWT_SECRET_VAR)
+                .map_err(|_| anyhow::anyhow!("{} must be set", JWT_SECRET_VAR))?;
+            if args.max_expires_in > presign::MAX_EXPIRY {
+                anyhow::bail!("presigned URLs can last at most 7 days");
+            }
+            let client = s3::client_for_bucket(&client, &args.location.bucket).await?;
+            let options = ServerOptions {
+                location: args.location,
+                default_expiry: args.expires_in.min(args.max_expires_in),
+                max_expiry: args.max_expires_in,
+                content_types: args.content_type,
+            };
+            server::serve(client, options, &secret, args.listen).await?
+        }
+    }
 
-    let constraint = BucketLocationConstraint::from("eu-west-1");
-    let cfg = CreateBucketConfiguration::builder()
-        .location_constraint(constraint)
-        .build();
+    Ok(())
+}
 
-    client
-        .create_bucket()
-        .bucket(bucket_name.hyphenated().to_string())
-        .create_bucket_configuration(cfg)
-        .send()
-        .await?;
-    println!("Created bucket {}", bucket_name.hyphenated().to_string());
-
-    let body = ByteStream::from_path(path).await;
-    let key = PREFIX.to_string() + "/" + &key;
-    client
-        .put_object()
-        .bucket(bucket_name.hyphenated().to_string())
-        .key(key)
-        .body(body.unwrap())
-        .send()
-        .await?;
+async fn put(client: &Client, args: &PutArgs) -> anyhow::Result<()> {
+    let name = args
+        .file
+        .file_name()
+        .expect("Path should always have a final component")
+        .to_string_lossy();
+    let object = args.object.options()?;
+
+    let (bucket_name, client, created) = match &args.bucket {
+        Some(bucket_name) => match bucket::bucket_region(client, bucket_name).await? {
+            Some(region) => {
+                println!("Using bucket {} in {}", bucket_name, region);
+                let client = s3::regional_client(client, &region);
+                (bucket_name.clone(), client, false)
+            }
+            None => {
+                bucket::create_bucket(client, bucket_name).await?;
+                (bucket_name.clone(), client.clone(), true)
+            }
+        },
+        None => {
+            let bucket_name = Uuid::new_v4().hyphenated().to_string();
+            bucket::create_bucket(client, &bucket_name).await?;
+            (bucket_name, client.clone(), true)
+        }
+    };
+
+    let prefix = args.prefix.trim_end_matches('/');
+    let key = if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", prefix, name)
+    };
+    upload::upload_file(
+        &client,
+        &bucket_name,
+        &key,
+        &args.file,
+        &args.transfer.options(),
+        &object,
+    )
+    .await?;
 
     let resp = client
         .list_objects_v2()
-        .bucket(bucket_name.hyphenated().to_string())
-        .prefix(PREFIX)
+        .bucket(&bucket_name)
+        .prefix(prefix)
         .send()
         .await?;
 
-    let keys: Vec<&str> = resp
-        .contents()
-        .unwrap_or_default()
-        .iter()
-        .map(|o| o.key().unwrap_or_default())
-        .collect();
-
     println!("Files in bucket:");
-    for key in keys {
-        println!("  {key}");
+    for object in resp.contents().unwrap_or_default() {
+        object::print_object(&client, &bucket_name, object.key().unwrap_or_default()).await?;
+    }
+
+    if args.cleanup {
+        client
+            .delete_object()
+            .bucket(&bucket_name)
+            .key(&key)
+            .send()
+            .await?;
+        println!("Deleted {}", key);
+        if created {
+            bucket::delete_bucket(&client, &bucket_name).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn get(client: &Client, args: &GetArgs) -> anyhow::Result<()> {
+    let key = &args.source.prefix;
+    let name = match key.rsplit('/').next() {
+        Some(name) if !name.is_empty() => name,
+        _ => anyhow::bail!("s3://{}/{} is not an object", args.source.bucket, key),
+    };
+
+    let path = match &args.destination {
+        Some(path) if path.is_dir() => path.join(name),
+        Some(path) => path.clone(),
+        None => PathBuf::from(name),
+    };
+
+    let client = s3::client_for_bucket(client, &args.source.bucket).await?;
+    download::download_file(
+        &client,
+        &args.source.bucket,
+        key,
+        &path,
+        &args.transfer.options(),
+    )
+    .await?;
+    println!(
+        "Downloaded s3://{}/{} to {}",
+        args.source.bucket,
+        key,
+        path.display()
+    );
+
+    Ok(())
+}
+
+async fn presign(client: &Client, args: &PresignArgs) -> anyhow::Result<()> {
+    // The URL has to be signed for the bucket's region.
+    let client = s3::client_for_bucket(client, &args.object.bucket).await?;
+    let presigned = presign::presign(
+        &client,
+        &args.object.bucket,
+        &args.object.prefix,
+        args.method,
+        args.expires_in,
+        args.content_type.as_deref(),
+    )
+    .await?;
+
+    println!("{}", presigned.url);
+    for (name, value) in &presigned.headers {
+        println!("{}: {}", name, value);
     }
 
     Ok(())
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use aws_sdk_s3::types::{ChecksumAlgorithm, ChecksumMode, ServerSideEncryption, StorageClass};
+use aws_sdk_s3::Client;
+
+pub enum Encryption {
+    // SSE-S3, keys managed by S3.
+    S3,
+    // SSE-KMS with the given key, or the account's aws/s3 key.
+    Kms(Option<String>),
+}
+
+// How uploaded objects are stored and described.
+#[derive(Default)]
+pub struct ObjectOptions {
+    pub encryption: Option<Encryption>,
+    // Have S3 store and check a SHA-256 checksum of the content.
+    pub checksum: bool,
+    // Guessed from the file's extension if not given.
+    pub content_type: Option<String>,
+    pub metadata: HashMap<String, String>,
+    pub storage_class: Option<StorageClass>,
+    pub tags: Vec<(String, String)>,
+}
+
+impl ObjectOptions {
+    pub fn content_type(&self, path: &Path) -> String {
+        match &self.content_type {
+            Some(content_type) => content_type.clone(),
+            None => mime_guess::from_path(path)
+                .first_or_octet_stream()
+                .to_string(),
+        }
+    }
+
+    pub fn server_side_encryption(&self) -> Option<ServerSideEncryption> {
+        match &self.encryption {
+            Some(Encryption::S3) => Some(ServerSideEncryption::Aes256),
+            Some(Encryption::Kms(_)) => Some(ServerSideEncryption::AwsKms),
+            None => None,
+        }
+    }
+
+    pub fn kms_key_id(&self) -> Option<String> {
+        match &self.encryption {
+            Some(Encryption::Kms(key_id)) => key_id.clone(),
+            _ => None,
+        }
+    }
+
+    pub fn checksum_algorithm(&self) -> Option<ChecksumAlgorithm> {
+        self.checksum.then_some(ChecksumAlgorithm::Sha256)
+    }
+
+    pub fn metadata(&self) -> Option<HashMap<String, String>> {
+        (!self.metadata.is_empty()).then(|| self.metadata.clone())
+    }
+
+    // Tags are sent as a URL encoded query string.
+    pub fn tagging(&self) -> Option<String> {
+        if self.tags.is_empty() {
+            return None;
+        }
+        let tags: Vec<String> = self
+            .tags
+            .iter()
+            .map(|(key, value)| {
+                format!(
+                    "{}={}",
+                    urlencoding::encode(key),
+                    urlencoding::encode(value)
+                )
+            })
+            .collect();
+        Some(tags.join("&"))
+    }
+}
+
+// Print an object's key with how it's stored, its checksum, metadata and tags.
+pub async fn print_object(client: &Client, bucket: &str, key: &str) -> anyhow::Result<()> {
+    let head = client
+        .head_object()
+        .bucket(bucket)
+        .key(key)
+        .checksum_mode(ChecksumMode::Enabled)
+        .send()
+        .await?;
+    let tagging = client
+        .get_object_tagging()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await?;
+
+    println!("  {}", key);
+    println!(
+        "      {} bytes, {}, {}",
+        head.content_length(),
+        head.content_type().unwrap_or("no content type"),
+        // HeadObject leaves out the storage class for STANDARD.
+        head.storage_class()
+            .map(|c| c.as_str())
+            .unwrap_or(StorageClass::Standard.as_str())
+    );
+
+    match (head.server_side_encryption(), head.ssekms_key_id()) {
+        (Some(sse), Some(key_id)) => println!("      encryption {} with {}", sse.as_str(), key_id),
+        (Some(sse), None) => println!("      encryption {}", sse.as_str()),
+        (None, _) => println!("      not encrypted"),
+    }
+    if let Some(checksum) = head.checksum_sha256() {
+        println!("      sha256 {}", checksum);
+    }
+
+    let mut metadata: Vec<_> = head.metadata().into_iter().flatten().collect();
+    metadata.sort();
+    for (name, value) in metadata {
+        println!("      metadata {}={}", name, value);
+    }
+    for tag in tagging.tag_set().unwrap_or_default() {
+        println!(
+            "      tag {}={}",
+            tag.key().unwrap_or_default(),
+            tag.value().unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use aws_sdk_s3::presigning::{PresignedRequest, PresigningConfig};
+use aws_sdk_s3::Client;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+// SigV4 presigned URLs can't last longer than a week.
+pub const MAX_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Method {
+    /// A URL to download the object
+    Get,
+    /// A URL to upload the object
+    Put,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Method::Get => write!(f, "GET"),
+            Method::Put => write!(f, "PUT"),
+        }
+    }
+}
+
+// Whoever uses the URL has to send the headers too, or the signature won't
+// match.
+#[derive(Debug, Serialize)]
+pub struct Presigned {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    // Seconds since the Unix epoch.
+    pub expires_at: u64,
+}
+
+// For a PUT the content type is signed, so the upload has to use it.  For a
+// GET it's the Content-Type S3 sends back, whatever the object was stored
+// with.
+pub async fn presign(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    method: Method,
+    expires_in: Duration,
+    content_type: Option<&str>,
+) -> anyhow::Result<Presigned> {
+    if expires_in > MAX_EXPIRY {
+        anyhow::bail!("presigned URLs can last at most 7 days");
+    }
+    let start = SystemTime::now();
+    let config = PresigningConfig::builder()
+        .start_time(start)
+        .expires_in(expires_in)
+        .build()?;
+
+    let request = match method {
+        Method::Get => {
+            client
+                .get_object()
+                .bucket(bucket)
+                .key(key)
+                .set_response_content_type(content_type.map(str::to_string))
+                .presigned(config)
+                .await?
+        }
+        Method::Put => {
+            client
+                .put_object()
+                .bucket(bucket)
+                .key(key)
+                .set_content_type(content_type.map(str::to_string))
+                .presigned(config)
+                .await?
+        }
+    };
+
+    let expires_at = (start + expires_in)
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs();
+    Ok(Presigned {
+        method,
+        url: request.uri().to_string(),
+        headers: headers(&request),
+        expires_at,
+    })
+}
+
+fn headers(request: &PresignedRequest) -> Vec<(String, String)> {
+    request
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| {
+            let value = value.to_str().ok()?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
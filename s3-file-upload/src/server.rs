@@ -0,0 +1,193 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use aws_sdk_s3::Client;
+use axum::extract::{FromRequestParts, State, TypedHeader};
+use axum::headers::authorization::Bearer;
+use axum::headers::Authorization;
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{async_trait, Json, RequestPartsExt, Router};
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::presign::{self, Method, Presigned};
+use crate::sync::S3Url;
+
+pub struct ServerOptions {
+    // Callers name keys relative to this, so they can't reach the rest of
+    // the bucket.
+    pub location: S3Url,
+    pub default_expiry: Duration,
+    pub max_expiry: Duration,
+    // Content types uploads may use, any when empty.
+    pub content_types: Vec<String>,
+}
+
+struct AppState {
+    client: Client,
+    options: ServerOptions,
+    key: DecodingKey,
+}
+
+// Tokens are HS256 JWTs signed with the shared secret, issued by whatever
+// already knows who the callers are.  The expiry is checked by decode.
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Claims {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let TypedHeader(Authorization(bearer)) = parts
+            .extract::<TypedHeader<Authorization<Bearer>>>()
+            .await
+            .map_err(|_| ApiError::unauthorized("missing or malformed Authorization header"))?;
+        let token_data = decode::<Claims>(bearer.token(), &state.key, &Validation::default())
+            .map_err(|_| ApiError::unauthorized("invalid token"))?;
+
+        Ok(token_data.claims)
+    }
+}
+
+#[derive(Deserialize)]
+struct PresignRequest {
+    method: Method,
+    key: String,
+    content_type: Option<String>,
+    // Seconds, the server's default when not given.
+    expires_in: Option<u64>,
+}
+
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn unauthorized(message: &str) -> Self {
+        Self {
+            status: StatusCode::UNAUTHORIZED,
+            message: message.to_string(),
+        }
+    }
+
+    fn bad_request(message: String) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    message: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            message: self.message,
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+pub async fn serve(
+    client: Client,
+    options: ServerOptions,
+    secret: &str,
+    address: SocketAddr,
+) -> anyhow::Result<()> {
+    let state = Arc::new(AppState {
+        client,
+        options,
+        key: DecodingKey::from_secret(secret.as_bytes()),
+    });
+    let app = Router::new()
+        .route("/presign", post(presign_handler))
+        .with_state(state);
+
+    println!("Starting server listening on: 'http://{}'", address);
+    axum::Server::bind(&address)
+        .serve(app.into_make_service())
+        .await?;
+
+    Ok(())
+}
+
+async fn presign_handler(
+    claims: Claims,
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<PresignRequest>,
+) -> Result<Json<Presigned>, ApiError> {
+    let options = &state.options;
+
+    if request.key.is_empty()
+        || request.key.starts_with('/')
+        || request.key.split('/').any(|part| part == "..")
+    {
+        return Err(ApiError::bad_request(format!(
+            "'{}' is not a valid key",
+            request.key
+        )));
+    }
+    let key = options.location.key(&request.key);
+
+    let expires_in = request
+        .expires_in
+        .map(Duration::from_secs)
+        .unwrap_or(options.default_expiry);
+    if expires_in > options.max_expiry {
+        return Err(ApiError::bad_request(format!(
+            "URLs can last at most {} seconds",
+            options.max_expiry.as_secs()
+        )));
+    }
+
+    if request.method == Method::Put && !options.content_types.is_empty() {
+        match &request.content_type {
+            Some(content_type) if options.content_types.contains(content_type) => {}
+            _ => {
+                return Err(ApiError::bad_request(format!(
+                    "uploads need a content type, one of {}",
+                    options.content_types.join(", ")
+                )))
+            }
+        }
+    }
+
+    let presigned = presign::presign(
+        &state.client,
+        &options.location.bucket,
+        &key,
+        request.method,
+        expires_in,
+        request.content_type.as_deref(),
+    )
+    .await
+    .map_err(|err| {
+        warn!("Failed to presign {} {}: {}", request.method, key, err);
+        ApiError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: "could not presign the request".to_string(),
+        }
+    })?;
+
+    info!(
+        "Presigned {} s3://{}/{} for {}",
+        request.method, options.location.bucket, key, claims.sub
+    );
+    Ok(Json(presigned))
+}
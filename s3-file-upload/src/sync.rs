@@ -0,0 +1,480 @@
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+
+use aws_sdk_s3::types::{Delete, ObjectIdentifier, ServerSideEncryption};
+use aws_sdk_s3::Client;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use walkdir::WalkDir;
+
+use crate::download;
+use crate::object::ObjectOptions;
+use crate::transfer::{e_tag_parts, file_e_tag, TransferOptions};
+use crate::upload;
+
+// DeleteObjects takes at most 1,000 keys per request.
+const DELETE_BATCH: usize = 1000;
+
+// s3://bucket/prefix, the prefix may be empty.
+#[derive(Clone, Debug)]
+pub struct S3Url {
+    pub bucket: String,
+    pub prefix: String,
+}
+
+impl S3Url {
+    // The key for a path relative to the prefix.
+    pub fn key(&self, relative: &str) -> String {
+        if self.prefix.is_empty() || self.prefix.ends_with('/') {
+            format!("{}{}", self.prefix, relative)
+        } else {
+            format!("{}/{}", self.prefix, relative)
+        }
+    }
+}
+
+// Either end of a sync.
+#[derive(Clone, Debug)]
+pub enum Location {
+    Local(PathBuf),
+    S3(S3Url),
+}
+
+impl FromStr for Location {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("s3://") {
+            Ok(Location::S3(s.parse()?))
+        } else {
+            Ok(Location::Local(PathBuf::from(s)))
+        }
+    }
+}
+
+impl FromStr for S3Url {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s
+            .strip_prefix("s3://")
+            .ok_or_else(|| format!("'{}' should start with s3://", s))?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(format!("'{}' has no bucket name", s));
+        }
+        Ok(Self {
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+        })
+    }
+}
+
+pub struct SyncOptions {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    // Delete whatever is at the destination but not at the source.
+    pub delete: bool,
+    pub dry_run: bool,
+}
+
+// Paths relative to the root of a sync, with / as the separator, are matched
+// against include and exclude globs.
+pub struct Filter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl Filter {
+    pub fn new(include: &[String], exclude: &[String]) -> anyhow::Result<Self> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(glob_set(include)?)
+        };
+        Ok(Self {
+            include,
+            exclude: glob_set(exclude)?,
+        })
+    }
+
+    pub fn matches(&self, relative: &str) -> bool {
+        let included = match &self.include {
+            Some(include) => include.is_match(relative),
+            None => true,
+        };
+        included && !self.exclude.is_match(relative)
+    }
+}
+
+fn glob_set(patterns: &[String]) -> anyhow::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+struct LocalFile {
+    path: PathBuf,
+    size: u64,
+    // Seconds since the epoch.
+    modified: i64,
+}
+
+pub struct RemoteObject {
+    pub size: u64,
+    pub e_tag: Option<String>,
+    // Seconds since the epoch.
+    pub last_modified: i64,
+}
+
+pub async fn sync_to_s3(
+    client: &Client,
+    source: &Path,
+    destination: &S3Url,
+    options: &SyncOptions,
+    transfer: &TransferOptions,
+    object_options: &ObjectOptions,
+) -> anyhow::Result<()> {
+    let filter = Filter::new(&options.include, &options.exclude)?;
+    let local = local_files(source, &filter)?;
+    let remote = list_objects(client, destination, &filter).await?;
+
+    let mut uploaded = 0;
+    for (relative, file) in &local {
+        let key = destination.key(relative);
+        let reason = match remote.get(relative) {
+            None => "new",
+            Some(object) => match changed(client, destination, &key, file, object, transfer).await?
+            {
+                Some(Change::Newer) if file.modified <= object.last_modified => continue,
+                Some(change) => change.reason(),
+                None => continue,
+            },
+        };
+
+        if options.dry_run {
+            println!("Would upload {} ({})", key, reason);
+        } else {
+            println!("Uploading {} ({})", key, reason);
+            upload::upload_file(
+                client,
+                &destination.bucket,
+                &key,
+                &file.path,
+                transfer,
+                object_options,
+            )
+            .await?;
+        }
+        uploaded += 1;
+    }
+
+    let extra: Vec<String> = remote
+        .keys()
+        .filter(|relative| !local.contains_key(*relative))
+        .map(|relative| destination.key(relative))
+        .collect();
+    if options.delete {
+        delete_objects(client, &destination.bucket, &extra, options.dry_run).await?;
+    } else if !extra.is_empty() {
+        println!(
+            "{} objects have no local file, use --delete to remove them",
+            extra.len()
+        );
+    }
+
+    println!(
+        "{} of {} files {}",
+        uploaded,
+        local.len(),
+        if options.dry_run {
+            "would be uploaded"
+        } else {
+            "uploaded"
+        }
+    );
+    Ok(())
+}
+
+pub async fn sync_from_s3(
+    client: &Client,
+    source: &S3Url,
+    destination: &Path,
+    options: &SyncOptions,
+    transfer: &TransferOptions,
+) -> anyhow::Result<()> {
+    let filter = Filter::new(&options.include, &options.exclude)?;
+    let remote = list_objects(client, source, &filter).await?;
+    let local = if destination.exists() {
+        local_files(destination, &filter)?
+    } else {
+        BTreeMap::new()
+    };
+
+    let mut downloaded = 0;
+    for (relative, object) in &remote {
+        let key = source.key(relative);
+        let reason = match local.get(relative) {
+            None => "new",
+            Some(file) => match changed(client, source, &key, file, object, transfer).await? {
+                Some(Change::Newer) if object.last_modified <= file.modified => continue,
+                Some(change) => change.reason(),
+                None => continue,
+            },
+        };
+
+        let path = key_path(destination, relative)?;
+        if options.dry_run {
+            println!("Would download {} to {} ({})", key, path.display(), reason);
+        } else {
+            println!("Downloading {} to {} ({})", key, path.display(), reason);
+            download::download_file(client, &source.bucket, &key, &path, transfer).await?;
+        }
+        downloaded += 1;
+    }
+
+    let extra: Vec<&LocalFile> = local
+        .iter()
+        .filter(|(relative, _)| !remote.contains_key(*relative))
+        .map(|(_, file)| file)
+        .collect();
+    if options.delete {
+        for file in &extra {
+            if options.dry_run {
+                println!("Would delete {}", file.path.display());
+            } else {
+                println!("Deleting {}", file.path.display());
+                std::fs::remove_file(&file.path)?;
+            }
+        }
+    } else if !extra.is_empty() {
+        println!(
+            "{} files have no object, use --delete to remove them",
+            extra.len()
+        );
+    }
+
+    println!(
+        "{} of {} objects {}",
+        downloaded,
+        remote.len(),
+        if options.dry_run {
+            "would be downloaded"
+        } else {
+            "downloaded"
+        }
+    );
+    Ok(())
+}
+
+// Regular files under the root, keyed by their path relative to it.
+fn local_files(root: &Path, filter: &Filter) -> anyhow::Result<BTreeMap<String, LocalFile>> {
+    let mut files = BTreeMap::new();
+    for entry in WalkDir::new(root) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let relative = relative_key(root, entry.path());
+        if !filter.matches(&relative) {
+            continue;
+        }
+
+        let metadata = entry.metadata()?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        files.insert(
+            relative,
+            LocalFile {
+                path: entry.path().to_path_buf(),
+                size: metadata.len(),
+                modified,
+            },
+        );
+    }
+    Ok(files)
+}
+
+// Where a key relative to the prefix goes under the root, refusing anything
+// that would end up outside it.
+fn key_path(root: &Path, relative: &str) -> anyhow::Result<PathBuf> {
+    let mut path = root.to_path_buf();
+    for part in relative.split('/').filter(|p| !p.is_empty()) {
+        match Path::new(part).components().next() {
+            Some(Component::Normal(_)) => path.push(part),
+            _ => anyhow::bail!("won't write {} outside {}", relative, root.display()),
+        }
+    }
+    Ok(path)
+}
+
+fn relative_key(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Objects under the prefix, keyed by their key relative to it.
+pub async fn list_objects(
+    client: &Client,
+    url: &S3Url,
+    filter: &Filter,
+) -> anyhow::Result<BTreeMap<String, RemoteObject>> {
+    let prefix = url.key("");
+    let mut objects = BTreeMap::new();
+    let mut token = None;
+    loop {
+        let resp = client
+            .list_objects_v2()
+            .bucket(&url.bucket)
+            .prefix(&prefix)
+            .set_continuation_token(token)
+            .send()
+            .await?;
+
+        for object in resp.contents().unwrap_or_default() {
+            let key = object.key().unwrap_or_default();
+            let relative = &key[prefix.len()..];
+            // Zero length "directory" placeholders made by the console.
+            if relative.is_empty() || relative.ends_with('/') || !filter.matches(relative) {
+                continue;
+            }
+            objects.insert(
+                relative.to_string(),
+                RemoteObject {
+                    size: object.size() as u64,
+                    e_tag: object.e_tag().map(|t| t.trim_matches('"').to_string()),
+                    last_modified: object.last_modified().map(|t| t.secs()).unwrap_or_default(),
+                },
+            );
+        }
+
+        token = resp.next_continuation_token().map(str::to_string);
+        if !resp.is_truncated() {
+            break;
+        }
+    }
+    Ok(objects)
+}
+
+enum Change {
+    Size,
+    Content,
+    // The content can't be compared, so go by which side was modified last.
+    Newer,
+}
+
+impl Change {
+    fn reason(&self) -> &'static str {
+        match self {
+            Change::Size => "size",
+            Change::Content => "content",
+            Change::Newer => "modified",
+        }
+    }
+}
+
+// How the local file and the object differ, or None if they're the same.
+async fn changed(
+    client: &Client,
+    url: &S3Url,
+    key: &str,
+    file: &LocalFile,
+    object: &RemoteObject,
+    transfer: &TransferOptions,
+) -> anyhow::Result<Option<Change>> {
+    if file.size != object.size {
+        return Ok(Some(Change::Size));
+    }
+
+    // A multipart ETag can only be reproduced if the parts were the same size
+    // as ours.
+    if let Some(e_tag) = &object.e_tag {
+        if let Some(parts) = e_tag_parts(e_tag) {
+            let local = file_e_tag(&file.path, file.size, transfer.part_size, parts).await?;
+            match local {
+                Some(local) if &local == e_tag => return Ok(None),
+                // The ETag of an SSE-KMS object isn't an MD5 at all, so only
+                // believe a difference if the object isn't encrypted with KMS.
+                Some(_) if !encrypted_with_kms(client, url, key).await? => {
+                    return Ok(Some(Change::Content))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(Some(Change::Newer))
+}
+
+async fn encrypted_with_kms(client: &Client, url: &S3Url, key: &str) -> anyhow::Result<bool> {
+    let head = client
+        .head_object()
+        .bucket(&url.bucket)
+        .key(key)
+        .send()
+        .await?;
+    Ok(matches!(
+        head.server_side_encryption(),
+        Some(ServerSideEncryption::AwsKms | ServerSideEncryption::AwsKmsDsse)
+    ))
+}
+
+pub async fn delete_objects(
+    client: &Client,
+    bucket: &str,
+    keys: &[String],
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    for key in keys {
+        if dry_run {
+            println!("Would delete {}", key);
+        } else {
+            println!("Deleting {}", key);
+        }
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    for batch in keys.chunks(DELETE_BATCH) {
+        let objects = batch
+            .iter()
+            .map(|key| ObjectIdentifier::builder().key(key).build())
+            .collect();
+        let resp = client
+            .delete_objects()
+            .bucket(bucket)
+            .delete(
+                Delete::builder()
+                    .set_objects(Some(objects))
+                    .quiet(true)
+                    .build(),
+            )
+            .send()
+            .await?;
+
+        let errors = resp.errors().unwrap_or_default();
+        for error in errors {
+            println!(
+                "Failed to delete {}: {}",
+                error.key().unwrap_or_default(),
+                error.message().unwrap_or_default()
+            );
+        }
+        if !errors.is_empty() {
+            anyhow::bail!("{} objects could not be deleted", errors.len());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use md5::{Digest, Md5};
+
+pub const MIB: u64 = 1024 * 1024;
+
+pub struct TransferOptions {
+    // Files no larger than this are transferred with a single request.
+    pub part_size: u64,
+    pub concurrency: usize,
+    pub retries: u32,
+}
+
+pub fn progress_bar(key: &str, size: u64) -> ProgressBar {
+    let style = ProgressStyle::with_template(
+        "{msg} [{bar:40}] {bytes}/{total_bytes} {binary_bytes_per_sec} ETA {eta}",
+    )
+    .expect("progress bar template should be valid")
+    .progress_chars("=> ");
+
+    ProgressBar::new(size)
+        .with_style(style)
+        .with_message(key.to_string())
+}
+
+// The ETag of an unencrypted object is the MD5 of its content, or for a
+// multipart upload the MD5 of the parts' MD5s followed by the part count.
+// Returns the part count, or Some(None) for a single part, and None if the
+// ETag isn't in either form.
+pub fn e_tag_parts(e_tag: &str) -> Option<Option<u64>> {
+    match e_tag.split_once('-') {
+        Some((_, parts)) => parts.parse().ok().map(Some),
+        None => Some(None),
+    }
+}
+
+// The ETag S3 would give the file if it was uploaded in the given number of
+// parts, or in a single request if that's None.  None if the parts couldn't
+// have been part_size long.
+pub async fn file_e_tag(
+    path: &Path,
+    size: u64,
+    part_size: u64,
+    parts: Option<u64>,
+) -> anyhow::Result<Option<String>> {
+    let (count, length) = match parts {
+        Some(parts) if size.div_ceil(part_size) != parts => return Ok(None),
+        Some(parts) => (parts, part_size),
+        None => (1, size),
+    };
+
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut file = File::open(path)?;
+        let mut digests = Vec::new();
+        let mut buffer = vec![0; MIB as usize];
+        for _ in 0..count {
+            let mut hasher = Md5::new();
+            let mut remaining = length;
+            while remaining > 0 {
+                let want = remaining.min(buffer.len() as u64) as usize;
+                let read = file.read(&mut buffer[..want])?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+                remaining -= read as u64;
+            }
+            digests.push(hasher.finalize());
+        }
+
+        if parts.is_none() {
+            return Ok(Some(format!("{:x}", digests[0])));
+        }
+        let mut hasher = Md5::new();
+        for digest in &digests {
+            hasher.update(digest);
+        }
+        Ok(Some(format!("{:x}-{}", hasher.finalize(), count)))
+    })
+    .await?
+}
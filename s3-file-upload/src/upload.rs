@@ -0,0 +1,241 @@
+use std::path::Path;
+
+use aws_common::{s3, Backoff};
+use aws_sdk_s3::error::DisplayErrorContext;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{ChecksumAlgorithm, CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::Client;
+use aws_smithy_http::byte_stream::Length;
+use futures::{StreamExt, TryStreamExt};
+use indicatif::ProgressBar;
+
+use crate::object::ObjectOptions;
+use crate::transfer::{progress_bar, TransferOptions, MIB};
+
+// S3 rejects uploads with more than 10,000 parts.
+const MAX_PARTS: u64 = 10_000;
+
+pub async fn upload_file(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    path: &Path,
+    options: &TransferOptions,
+    object: &ObjectOptions,
+) -> anyhow::Result<()> {
+    let size = tokio::fs::metadata(path).await?.len();
+    let progress = progress_bar(key, size);
+
+    if size <= options.part_size {
+        client
+            .put_object()
+            .bucket(bucket)
+            .key(key)
+            .content_type(object.content_type(path))
+            .set_server_side_encryption(object.server_side_encryption())
+            .set_ssekms_key_id(object.kms_key_id())
+            .set_checksum_algorithm(object.checksum_algorithm())
+            .set_metadata(object.metadata())
+            .set_storage_class(object.storage_class.clone())
+            .set_tagging(object.tagging())
+            .body(ByteStream::from_path(path).await?)
+            .send()
+            .await?;
+        progress.inc(size);
+    } else {
+        upload_multipart(client, bucket, key, path, size, options, object, &progress).await?;
+    }
+
+    progress.finish();
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn upload_multipart(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    path: &Path,
+    size: u64,
+    options: &TransferOptions,
+    object: &ObjectOptions,
+    progress: &ProgressBar,
+) -> anyhow::Result<()> {
+    let part_size = part_size(size, options.part_size);
+    if part_size != options.part_size {
+        progress.println(format!(
+            "Using {} MiB parts to stay within {} parts",
+            part_size / MIB,
+            MAX_PARTS
+        ));
+    }
+
+    let resp = client
+        .create_multipart_upload()
+        .bucket(bucket)
+        .key(key)
+        .content_type(object.content_type(path))
+        .set_server_side_encryption(object.server_side_encryption())
+        .set_ssekms_key_id(object.kms_key_id())
+        .set_checksum_algorithm(object.checksum_algorithm())
+        .set_metadata(object.metadata())
+        .set_storage_class(object.storage_class.clone())
+        .set_tagging(object.tagging())
+        .send()
+        .await?;
+    let upload_id = resp
+        .upload_id()
+        .expect("should always get an upload ID")
+        .to_string();
+
+    let result = async {
+        let count = size.div_ceil(part_size);
+        let mut parts: Vec<CompletedPart> = futures::stream::iter(1..=count)
+            .map(|n| {
+                let offset = (n - 1) * part_size;
+                let length = part_size.min(size - offset);
+                let part = Part {
+                    number: n as i32,
+                    offset,
+                    length,
+                };
+                upload_part(
+                    client,
+                    bucket,
+                    key,
+                    &upload_id,
+                    path,
+                    part,
+                    object.checksum_algorithm(),
+                    options,
+                    progress,
+                )
+            })
+            .buffer_unordered(options.concurrency.max(1))
+            .try_collect()
+            .await?;
+        parts.sort_by_key(|p| p.part_number());
+
+        client
+            .complete_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(&upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await?;
+        Ok(())
+    }
+    .await;
+
+    // Parts of an incomplete upload are stored, and charged for, until the
+    // upload is aborted.
+    if result.is_err() {
+        progress.abandon();
+        match client
+            .abort_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(&upload_id)
+            .send()
+            .await
+        {
+            Ok(_) => println!("Aborted upload of {}", key),
+            Err(err) => println!(
+                "Failed to abort upload {} of {}: {}",
+                upload_id,
+                key,
+                DisplayErrorContext(&err)
+            ),
+        }
+    }
+
+    result
+}
+
+struct Part {
+    number: i32,
+    offset: u64,
+    length: u64,
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn upload_part(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    path: &Path,
+    part: Part,
+    checksum: Option<ChecksumAlgorithm>,
+    options: &TransferOptions,
+    progress: &ProgressBar,
+) -> anyhow::Result<CompletedPart> {
+    let mut backoff = Backoff::new();
+    let mut attempt = 0;
+    loop {
+        // Stream the part straight from the file rather than holding it in
+        // memory, the stream can't be rewound so each attempt opens a new one.
+        let body = ByteStream::read_from()
+            .path(path)
+            .offset(part.offset)
+            .length(Length::Exact(part.length))
+            .build()
+            .await?;
+
+        let result = client
+            .upload_part()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part.number)
+            .content_length(part.length as i64)
+            .set_checksum_algorithm(checksum.clone())
+            .body(body)
+            .send()
+            .await;
+
+        match result {
+            Ok(resp) => {
+                progress.inc(part.length);
+                return Ok(CompletedPart::builder()
+                    .part_number(part.number)
+                    .set_e_tag(resp.e_tag().map(str::to_string))
+                    .set_checksum_sha256(resp.checksum_sha256().map(str::to_string))
+                    .build());
+            }
+            Err(err) if attempt < options.retries && s3::is_retryable(&err) => {
+                attempt += 1;
+                progress.println(format!(
+                    "Part {} failed, retrying in {} seconds: {}",
+                    part.number,
+                    backoff.delay().as_secs(),
+                    DisplayErrorContext(&err)
+                ));
+                backoff.wait().await;
+            }
+            Err(err) => {
+                return Err(anyhow::anyhow!(
+                    "part {} failed after {} attempts: {}",
+                    part.number,
+                    attempt + 1,
+                    DisplayErrorContext(&err)
+                ))
+            }
+        }
+    }
+}
+
+// The requested part size, or the smallest whole number of MiB that keeps
+// the upload within the part limit.
+fn part_size(size: u64, requested: u64) -> u64 {
+    if size.div_ceil(requested) <= MAX_PARTS {
+        requested
+    } else {
+        size.div_ceil(MAX_PARTS).div_ceil(MIB) * MIB
+    }
+}
@@ -0,0 +1,122 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use aws_sdk_ec2::Client;
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::rules::{apply_changes, describe_rules, Change, Direction, Peer, Rule, RuleKey};
+
+// Rules created by `allow` carry their expiry in the description, which is
+// what `sweep` looks for.  Anything else in the group is left alone.
+const DESCRIPTION_PREFIX: &str = "Temporary access until ";
+
+const CHECK_IP_URL: &str = "https://checkip.amazonaws.com";
+
+pub async fn allow(
+    client: &Client,
+    group_id: &str,
+    ip: Option<IpAddr>,
+    port: i32,
+    duration: Duration,
+) -> anyhow::Result<()> {
+    let ip = match ip {
+        Some(ip) => ip,
+        None => public_ip().await?,
+    };
+    let peer = match ip {
+        IpAddr::V4(ip) => Peer::Cidr(format!("{}/32", ip)),
+        IpAddr::V6(ip) => Peer::CidrIpv6(format!("{}/128", ip)),
+    };
+
+    let expires = Utc::now() + chrono::Duration::from_std(duration)?;
+    let rule = Rule {
+        key: RuleKey {
+            protocol: "tcp".to_string(),
+            from_port: port,
+            to_port: port,
+            peer,
+        },
+        description: Some(format!(
+            "{}{}",
+            DESCRIPTION_PREFIX,
+            expires.to_rfc3339_opts(SecondsFormat::Secs, true)
+        )),
+    };
+
+    // Asking again from the same address just pushes the expiry back.  A rule
+    // that isn't temporary is left alone, giving it an expiry would have
+    // sweep revoke it.
+    let live = describe_rules(client, Some(group_id)).await?;
+    let change = match live
+        .iter()
+        .find(|l| l.direction == Direction::Ingress && l.rule.key == rule.key)
+    {
+        Some(existing) if !is_temporary(&existing.rule) => {
+            println!(
+                "{}: {} already has permanent access ({})",
+                group_id, rule.key, existing.id
+            );
+            return Ok(());
+        }
+        Some(existing) => Change::Describe(Direction::Ingress, existing.id.clone(), rule),
+        None => Change::Authorize(Direction::Ingress, rule),
+    };
+
+    println!("{}: {}", group_id, change);
+    apply_changes(client, group_id, &[change]).await?;
+
+    Ok(())
+}
+
+pub async fn sweep(client: &Client, group_id: Option<&str>, dry_run: bool) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let live = describe_rules(client, group_id).await?;
+
+    let mut swept = 0;
+    for rule in live {
+        let Some(expires) = rule.rule.description.as_deref().and_then(expiry) else {
+            continue;
+        };
+        if expires > now {
+            continue;
+        }
+
+        let change = Change::Revoke(rule.direction, rule.id, rule.rule);
+        println!("{}: {}", rule.group_id, change);
+        if !dry_run {
+            apply_changes(client, &rule.group_id, &[change]).await?;
+        }
+        swept += 1;
+    }
+
+    if swept == 0 {
+        println!("No expired temporary rules.");
+    }
+
+    Ok(())
+}
+
+fn is_temporary(rule: &Rule) -> bool {
+    rule.description
+        .as_deref()
+        .is_some_and(|d| d.starts_with(DESCRIPTION_PREFIX))
+}
+
+fn expiry(description: &str) -> Option<DateTime<Utc>> {
+    let timestamp = description.strip_prefix(DESCRIPTION_PREFIX)?;
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+async fn public_ip() -> anyhow::Result<IpAddr> {
+    let body = reqwest::get(CHECK_IP_URL)
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let ip = body.trim().parse()?;
+    println!("Detected public IP {}", ip);
+    Ok(ip)
+}
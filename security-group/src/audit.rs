@@ -0,0 +1,230 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use aws_sdk_ec2::Client;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::rules::{describe_all_groups, describe_rules, Direction, Group, LiveRule, Peer};
+
+// Ports that should never be open to the whole Internet.
+const ADMIN_PORTS: &[(i32, &str)] = &[
+    (22, "SSH"),
+    (23, "Telnet"),
+    (135, "RPC"),
+    (445, "SMB"),
+    (1433, "SQL Server"),
+    (2375, "Docker"),
+    (3306, "MySQL"),
+    (3389, "RDP"),
+    (5432, "PostgreSQL"),
+    (5900, "VNC"),
+    (6379, "Redis"),
+    (9200, "Elasticsearch"),
+    (27017, "MongoDB"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Low => write!(f, "LOW"),
+            Severity::Medium => write!(f, "MEDIUM"),
+            Severity::High => write!(f, "HIGH"),
+            Severity::Critical => write!(f, "CRITICAL"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub group_id: String,
+    pub group_name: String,
+    pub vpc_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule_id: Option<String>,
+    pub message: String,
+}
+
+pub async fn audit(client: &Client, min_severity: Severity, json: bool) -> anyhow::Result<()> {
+    let groups = describe_all_groups(client).await?;
+    let rules = describe_rules(client, None).await?;
+    let in_use = groups_in_use(client).await?;
+
+    let mut findings = Vec::new();
+    for rule in &rules {
+        if let Some(group) = groups.get(&rule.group_id) {
+            findings.extend(check_rule(rule, group, &groups));
+        }
+    }
+
+    for (id, group) in &groups {
+        // The default group can't be deleted, so there's no point flagging it.
+        if !group.is_default && !in_use.contains(id) {
+            findings.push(finding(
+                Severity::Low,
+                id,
+                group,
+                None,
+                "not attached to any network interface".to_string(),
+            ));
+        }
+    }
+
+    findings.retain(|f| f.severity >= min_severity);
+    findings.sort_by(|a, b| {
+        b.severity
+            .cmp(&a.severity)
+            .then_with(|| a.group_id.cmp(&b.group_id))
+            .then_with(|| a.rule_id.cmp(&b.rule_id))
+    });
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&findings)?);
+        return Ok(());
+    }
+
+    if findings.is_empty() {
+        println!("No findings in {} security groups.", groups.len());
+        return Ok(());
+    }
+    for f in &findings {
+        println!(
+            "{:<8}  {}  {}  {}{}",
+            f.severity,
+            f.group_id,
+            f.group_name,
+            f.message,
+            f.rule_id
+                .as_deref()
+                .map(|id| format!(" ({})", id))
+                .unwrap_or_default()
+        );
+    }
+    println!(
+        "{} findings in {} security groups.",
+        findings.len(),
+        groups.len()
+    );
+
+    Ok(())
+}
+
+fn check_rule(rule: &LiveRule, group: &Group, groups: &HashMap<String, Group>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let key = &rule.rule.key;
+    let rule_id = Some(rule.id.as_str());
+
+    if rule.direction == Direction::Ingress {
+        let world = match &key.peer {
+            Peer::Cidr(cidr) | Peer::CidrIpv6(cidr) => cidr == "0.0.0.0/0" || cidr == "::/0",
+            _ => false,
+        };
+
+        if key.protocol == "-1" {
+            if let Peer::Cidr(_) | Peer::CidrIpv6(_) = key.peer {
+                let severity = if world {
+                    Severity::Critical
+                } else {
+                    Severity::Medium
+                };
+                findings.push(finding(
+                    severity,
+                    &rule.group_id,
+                    group,
+                    rule_id,
+                    format!("all protocols allowed from {}", key.peer),
+                ));
+            }
+        } else if world && key.protocol == "tcp" {
+            // One finding for the rule however many admin ports its range
+            // covers, so a 0-65535 rule doesn't fill the report.
+            let exposed: Vec<String> = ADMIN_PORTS
+                .iter()
+                .filter(|(port, _)| key.from_port <= *port && *port <= key.to_port)
+                .map(|(port, service)| format!("{} ({})", service, port))
+                .collect();
+            if !exposed.is_empty() {
+                findings.push(finding(
+                    Severity::High,
+                    &rule.group_id,
+                    group,
+                    rule_id,
+                    format!("{} open to {}", exposed.join(", "), key.peer),
+                ));
+            }
+        }
+    }
+
+    // A reference to a group in another account can't be checked from here.
+    if let Peer::Group(id) = &key.peer {
+        let same_account = match rule.peer_owner_id.as_deref() {
+            Some(owner) => owner == group.owner_id,
+            None => true,
+        };
+        if same_account && !groups.contains_key(id) {
+            findings.push(finding(
+                Severity::Medium,
+                &rule.group_id,
+                group,
+                rule_id,
+                format!("{} rule references deleted group {}", rule.direction, id),
+            ));
+        }
+    }
+
+    findings
+}
+
+fn finding(
+    severity: Severity,
+    group_id: &str,
+    group: &Group,
+    rule_id: Option<&str>,
+    message: String,
+) -> Finding {
+    Finding {
+        severity,
+        group_id: group_id.to_string(),
+        group_name: group.name.clone(),
+        vpc_id: group.vpc_id.clone(),
+        rule_id: rule_id.map(str::to_string),
+        message,
+    }
+}
+
+async fn groups_in_use(client: &Client) -> anyhow::Result<HashSet<String>> {
+    let mut in_use = HashSet::new();
+    let mut next_token = None;
+    loop {
+        let resp = client
+            .describe_network_interfaces()
+            .set_next_token(next_token)
+            .send()
+            .await?;
+
+        for eni in resp.network_interfaces().unwrap_or_default() {
+            for group in eni.groups().unwrap_or_default() {
+                if let Some(id) = group.group_id() {
+                    in_use.insert(id.to_string());
+                }
+            }
+        }
+
+        next_token = resp.next_token().map(str::to_string);
+        if next_token.is_none() {
+            break;
+        }
+    }
+
+    Ok(in_use)
+}
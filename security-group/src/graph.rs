@@ -0,0 +1,210 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use aws_sdk_ec2::Client;
+use clap::ValueEnum;
+
+use crate::rules::{describe_all_groups, describe_rules, Direction, Group, Peer};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    Dot,
+    Mermaid,
+}
+
+struct Node {
+    label: String,
+    vpc_id: String,
+}
+
+// An edge points from the group that can start a connection to the group
+// that accepts it, labelled with every protocol/port allowed between them.
+struct Graph {
+    nodes: BTreeMap<String, Node>,
+    edges: BTreeMap<(String, String), BTreeSet<String>>,
+}
+
+pub async fn export(
+    client: &Client,
+    format: Format,
+    vpc_id: Option<&str>,
+    connected_only: bool,
+) -> anyhow::Result<()> {
+    let groups = describe_all_groups(client).await?;
+    let rules = describe_rules(client, None).await?;
+
+    let mut graph = Graph {
+        nodes: BTreeMap::new(),
+        edges: BTreeMap::new(),
+    };
+
+    for rule in &rules {
+        let Peer::Group(peer) = &rule.rule.key.peer else {
+            continue;
+        };
+        let Some(group) = groups.get(&rule.group_id) else {
+            continue;
+        };
+        if vpc_id.is_some_and(|vpc| vpc != group.vpc_id) {
+            continue;
+        }
+
+        let (from, to) = match rule.direction {
+            Direction::Ingress => (peer.clone(), rule.group_id.clone()),
+            Direction::Egress => (rule.group_id.clone(), peer.clone()),
+        };
+        graph
+            .edges
+            .entry((from, to))
+            .or_default()
+            .insert(rule.rule.key.ports_label());
+    }
+
+    let connected: BTreeSet<&String> = graph
+        .edges
+        .keys()
+        .flat_map(|(from, to)| [from, to])
+        .collect();
+    for (id, group) in &groups {
+        if vpc_id.is_some_and(|vpc| vpc != group.vpc_id) {
+            continue;
+        }
+        if connected_only && !connected.contains(id) {
+            continue;
+        }
+        graph.nodes.insert(id.clone(), node(id, Some(group)));
+    }
+    // Referenced groups in another VPC, in another account, or deleted.
+    for id in connected {
+        if !graph.nodes.contains_key(id) {
+            graph.nodes.insert(id.clone(), node(id, groups.get(id)));
+        }
+    }
+
+    let output = match format {
+        Format::Dot => to_dot(&graph),
+        Format::Mermaid => to_mermaid(&graph),
+    };
+    print!("{}", output);
+
+    Ok(())
+}
+
+fn node(id: &str, group: Option<&Group>) -> Node {
+    match group {
+        Some(group) => Node {
+            label: format!("{}\n{}", group.name, id),
+            vpc_id: group.vpc_id.clone(),
+        },
+        None => Node {
+            label: format!("{}\n(unknown)", id),
+            vpc_id: String::new(),
+        },
+    }
+}
+
+fn by_vpc(graph: &Graph) -> BTreeMap<&str, Vec<(&String, &Node)>> {
+    let mut vpcs: BTreeMap<&str, Vec<(&String, &Node)>> = BTreeMap::new();
+    for (id, node) in &graph.nodes {
+        vpcs.entry(node.vpc_id.as_str())
+            .or_default()
+            .push((id, node));
+    }
+    vpcs
+}
+
+fn to_dot(graph: &Graph) -> String {
+    let escape = |s: &str| {
+        s.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    };
+
+    let mut out = String::new();
+    writeln!(out, "digraph security_groups {{").unwrap();
+    writeln!(out, "    rankdir=LR;").unwrap();
+    writeln!(out, "    node [shape=box];").unwrap();
+
+    for (vpc_id, nodes) in by_vpc(graph) {
+        let indent = if vpc_id.is_empty() {
+            "    "
+        } else {
+            writeln!(out, "    subgraph \"cluster_{}\" {{", escape(vpc_id)).unwrap();
+            writeln!(out, "        label=\"{}\";", escape(vpc_id)).unwrap();
+            "        "
+        };
+        for (id, node) in nodes {
+            writeln!(
+                out,
+                "{}\"{}\" [label=\"{}\"];",
+                indent,
+                escape(id),
+                escape(&node.label)
+            )
+            .unwrap();
+        }
+        if !vpc_id.is_empty() {
+            writeln!(out, "    }}").unwrap();
+        }
+    }
+
+    for ((from, to), labels) in &graph.edges {
+        let label = labels.iter().cloned().collect::<Vec<_>>().join(", ");
+        writeln!(
+            out,
+            "    \"{}\" -> \"{}\" [label=\"{}\"];",
+            escape(from),
+            escape(to),
+            escape(&label)
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+fn to_mermaid(graph: &Graph) -> String {
+    // Mermaid IDs can't contain hyphens, and labels need HTML style escapes.
+    let id = |s: &str| s.replace(['-', '/'], "_");
+    let escape = |s: &str| s.replace('"', "#quot;").replace('\n', "<br/>");
+
+    let mut out = String::new();
+    writeln!(out, "flowchart LR").unwrap();
+
+    for (vpc_id, nodes) in by_vpc(graph) {
+        let indent = if vpc_id.is_empty() {
+            "    "
+        } else {
+            writeln!(out, "    subgraph {}[\"{}\"]", id(vpc_id), escape(vpc_id)).unwrap();
+            "        "
+        };
+        for (group_id, node) in nodes {
+            writeln!(
+                out,
+                "{}{}[\"{}\"]",
+                indent,
+                id(group_id),
+                escape(&node.label)
+            )
+            .unwrap();
+        }
+        if !vpc_id.is_empty() {
+            writeln!(out, "    end").unwrap();
+        }
+    }
+
+    for ((from, to), labels) in &graph.edges {
+        let label = labels.iter().cloned().collect::<Vec<_>>().join(", ");
+        writeln!(
+            out,
+            "    {} -->|\"{}\"| {}",
+            id(from),
+            escape(&label),
+            id(to)
+        )
+        .unwrap();
+    }
+
+    out
+}
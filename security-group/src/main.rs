@@ -1,11 +1,96 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
 use aws_config::meta::region::RegionProviderChain;
 use aws_sdk_ec2::{
     types::{IpPermission, UserIdGroupPair},
-    Client, Error,
+    Client,
 };
+use clap::{Args, Parser, Subcommand};
+
+mod access;
+mod audit;
+mod graph;
+mod rules;
+
+#[derive(Parser)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a VPC containing SG-One and SG-Two, and trust SG-One from
+    /// SG-Two.  This is what happens without a command
+    Create,
+    /// Reconcile security groups and their rules with a TOML rules file
+    Apply(ApplyArgs),
+    /// Scan every security group in the region for overly permissive rules
+    Audit(AuditArgs),
+    /// Allow SSH from this machine's public IP address for a limited time
+    Allow(AllowArgs),
+    /// Revoke temporary rules created by allow that have expired
+    Sweep(SweepArgs),
+    /// Export which security groups trust which as a Graphviz or Mermaid graph
+    Graph(GraphArgs),
+}
+
+#[derive(Args)]
+struct ApplyArgs {
+    file: PathBuf,
+    /// Show the changes that would be made without making them
+    #[clap(long)]
+    dry_run: bool,
+}
+
+#[derive(Args)]
+struct AuditArgs {
+    /// Only report findings at or above this severity
+    #[clap(long, value_enum, default_value = "low")]
+    min_severity: audit::Severity,
+    /// Print the findings as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+struct AllowArgs {
+    group_id: String,
+    /// The address to allow, detected automatically if not given
+    #[clap(long)]
+    ip: Option<IpAddr>,
+    #[clap(long, default_value_t = 22)]
+    port: i32,
+    /// How long the rule should last, for example "30m" or "2h"
+    #[clap(long, default_value = "1h", value_parser = humantime::parse_duration)]
+    expires_in: Duration,
+}
+
+#[derive(Args)]
+struct SweepArgs {
+    /// Only sweep this group, otherwise every group in the region
+    group_id: Option<String>,
+    /// Show the rules that would be revoked without revoking them
+    #[clap(long)]
+    dry_run: bool,
+}
+
+#[derive(Args)]
+struct GraphArgs {
+    #[clap(long, value_enum, default_value = "dot")]
+    format: graph::Format,
+    /// Only include groups in this VPC
+    #[clap(long)]
+    vpc_id: Option<String>,
+    /// Leave out groups that don't reference or aren't referenced by another group
+    #[clap(long)]
+    connected_only: bool,
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Error> {
+async fn main() -> anyhow::Result<()> {
     env_logger::init();
 
     let region_provider = RegionProviderChain::default_provider().or_else("eu-west-1");
@@ -13,6 +98,32 @@ async fn main() -> Result<(), Error> {
     let config = aws_config::from_env().region(region_provider).load().await;
     let client = Client::new(&config);
 
+    let cli = Cli::parse();
+    match cli.command.unwrap_or(Command::Create) {
+        Command::Create => create_groups(&client).await?,
+        Command::Apply(args) => rules::apply_file(&client, &args.file, args.dry_run).await?,
+        Command::Audit(args) => audit::audit(&client, args.min_severity, args.json).await?,
+        Command::Allow(args) => {
+            access::allow(&client, &args.group_id, args.ip, args.port, args.expires_in).await?
+        }
+        Command::Sweep(args) => {
+            access::sweep(&client, args.group_id.as_deref(), args.dry_run).await?
+        }
+        Command::Graph(args) => {
+            graph::export(
+                &client,
+                args.format,
+                args.vpc_id.as_deref(),
+                args.connected_only,
+            )
+            .await?
+        }
+    }
+
+    Ok(())
+}
+
+async fn create_groups(client: &Client) -> anyhow::Result<()> {
     let resp = client.create_vpc().cidr_block("10.0.0.0/16").send().await?;
 
     let vpcid = resp
@@ -0,0 +1,837 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use aws_sdk_ec2::types::{
+    Filter, IpPermission, IpRange, Ipv6Range, PrefixListId, SecurityGroupRule,
+    SecurityGroupRuleDescription, UserIdGroupPair,
+};
+use aws_sdk_ec2::Client;
+use serde::Deserialize;
+
+// The file format, for example:
+//
+//   vpc_id = "vpc-0123456789abcdef0"
+//
+//   [[group]]
+//   name = "web"
+//   description = "Web tier"
+//
+//   [[group.ingress]]
+//   protocol = "tcp"
+//   ports = 443
+//   cidr = "0.0.0.0/0"
+//   description = "HTTPS from anywhere"
+//
+//   [[group]]
+//   name = "app"
+//   description = "Application tier"
+//   egress = []  # Present means reconcile egress, empty means remove the default allow all
+//
+//   [[group.ingress]]
+//   protocol = "tcp"
+//   ports = "8000-8100"
+//   group = "web"  # A group from this file, or a sg-... ID
+//
+//   [[group.ingress]]
+//   protocol = "icmp"
+//   ports = "8-0"  # For ICMP the type and code, -1 for any
+//   cidr = "10.0.0.0/8"
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleFile {
+    pub vpc_id: String,
+    #[serde(rename = "group", default)]
+    pub groups: Vec<GroupSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroupSpec {
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub ingress: Vec<RuleSpec>,
+    // None leaves whatever egress rules the group has alone.
+    pub egress: Option<Vec<RuleSpec>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleSpec {
+    pub protocol: String,
+    pub ports: Option<PortSpec>,
+    pub cidr: Option<String>,
+    pub cidr_ipv6: Option<String>,
+    pub prefix_list: Option<String>,
+    pub group: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum PortSpec {
+    Single(i32),
+    Range(String),
+}
+
+impl RuleFile {
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let input = std::fs::read_to_string(path)?;
+        let file: RuleFile = toml::from_str(&input)?;
+
+        let mut names = Vec::new();
+        for group in &file.groups {
+            if names.contains(&group.name) {
+                anyhow::bail!("group '{}' is defined more than once", group.name);
+            }
+            names.push(group.name.clone());
+        }
+
+        Ok(file)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Direction {
+    Ingress,
+    Egress,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Ingress => write!(f, "ingress"),
+            Direction::Egress => write!(f, "egress"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Peer {
+    Cidr(String),
+    CidrIpv6(String),
+    PrefixList(String),
+    Group(String),
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Peer::Cidr(cidr) | Peer::CidrIpv6(cidr) => write!(f, "{}", cidr),
+            Peer::PrefixList(id) | Peer::Group(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+// What makes a rule unique as far as EC2 is concerned, the description
+// can be changed in place without revoking the rule.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RuleKey {
+    pub protocol: String,
+    pub from_port: i32,
+    pub to_port: i32,
+    pub peer: Peer,
+}
+
+impl RuleKey {
+    // The protocol and ports without the peer, e.g. "tcp/443".
+    pub fn ports_label(&self) -> String {
+        match self.protocol.as_str() {
+            "-1" => "all".to_string(),
+            "tcp" | "udp" if self.from_port == self.to_port => {
+                format!("{}/{}", self.protocol, self.from_port)
+            }
+            "tcp" | "udp" => format!("{}/{}-{}", self.protocol, self.from_port, self.to_port),
+            _ if self.from_port == -1 => self.protocol.clone(),
+            _ if self.to_port == -1 => format!("{} type {}", self.protocol, self.from_port),
+            _ => format!(
+                "{} type {} code {}",
+                self.protocol, self.from_port, self.to_port
+            ),
+        }
+    }
+}
+
+impl fmt::Display for RuleKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.ports_label(), self.peer)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    pub key: RuleKey,
+    pub description: Option<String>,
+}
+
+pub struct LiveRule {
+    pub id: String,
+    pub group_id: String,
+    pub direction: Direction,
+    pub rule: Rule,
+    // The account that owns a referenced group, which may not be ours.
+    pub peer_owner_id: Option<String>,
+}
+
+pub enum Change {
+    Authorize(Direction, Rule),
+    Revoke(Direction, String, Rule),
+    Describe(Direction, String, Rule),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Authorize(direction, rule) => write!(f, "+ {} {}", direction, rule.key)?,
+            Change::Revoke(direction, id, rule) => {
+                write!(f, "- {} {} ({})", direction, rule.key, id)?
+            }
+            Change::Describe(direction, id, rule) => {
+                write!(f, "~ {} {} ({})", direction, rule.key, id)?
+            }
+        }
+        if let Some(description) = self.rule().description.as_deref() {
+            write!(f, " \"{}\"", description)?;
+        }
+        Ok(())
+    }
+}
+
+impl Change {
+    fn rule(&self) -> &Rule {
+        match self {
+            Change::Authorize(_, rule)
+            | Change::Revoke(_, _, rule)
+            | Change::Describe(_, _, rule) => rule,
+        }
+    }
+}
+
+pub fn normalise_protocol(protocol: &str) -> String {
+    match protocol.to_lowercase().as_str() {
+        "all" | "-1" => "-1".to_string(),
+        "6" => "tcp".to_string(),
+        "17" => "udp".to_string(),
+        "1" => "icmp".to_string(),
+        "icmpv6" => "58".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn parse_ports(protocol: &str, ports: Option<&PortSpec>) -> anyhow::Result<(i32, i32)> {
+    if protocol == "icmp" || protocol == "58" {
+        return parse_icmp(ports);
+    }
+
+    let (from, to) = match ports {
+        None if protocol == "tcp" || protocol == "udp" => (0, 65535),
+        None => (-1, -1),
+        Some(_) if protocol == "-1" => {
+            anyhow::bail!("ports cannot be set when the protocol is all")
+        }
+        Some(PortSpec::Single(port)) => (*port, *port),
+        Some(PortSpec::Range(range)) => match split_range(range) {
+            (from, Some(to)) => (from.parse()?, to.parse()?),
+            (port, None) => {
+                let port = port.parse()?;
+                (port, port)
+            }
+        },
+    };
+
+    if from > to {
+        anyhow::bail!("port range {}-{} is backwards", from, to);
+    }
+    Ok((from, to))
+}
+
+// For ICMP EC2 uses the ports for the type and code, with -1 for any.  A
+// single number is a type with any code.
+fn parse_icmp(ports: Option<&PortSpec>) -> anyhow::Result<(i32, i32)> {
+    let (icmp_type, code) = match ports {
+        None => (-1, -1),
+        Some(PortSpec::Single(icmp_type)) => (*icmp_type, -1),
+        Some(PortSpec::Range(range)) => match split_range(range) {
+            (icmp_type, Some(code)) => (icmp_type.parse()?, code.parse()?),
+            (icmp_type, None) => (icmp_type.parse()?, -1),
+        },
+    };
+
+    for value in [icmp_type, code] {
+        if !(-1..=255).contains(&value) {
+            anyhow::bail!(
+                "ICMP type and code must be between -1 and 255, got {}",
+                value
+            );
+        }
+    }
+    if icmp_type == -1 && code != -1 {
+        anyhow::bail!("ICMP code {} needs a type", code);
+    }
+    Ok((icmp_type, code))
+}
+
+// Split "from-to" without taking the sign of a leading -1 as the separator.
+fn split_range(range: &str) -> (&str, Option<&str>) {
+    let range = range.trim();
+    match range.char_indices().skip(1).find(|(_, c)| *c == '-') {
+        Some((i, _)) => (range[..i].trim(), Some(range[i + 1..].trim())),
+        None => (range, None),
+    }
+}
+
+impl RuleSpec {
+    // Group names are looked up in `groups`, anything not found there is
+    // assumed to already be a security group ID.
+    pub fn resolve(&self, groups: &HashMap<String, String>) -> anyhow::Result<Rule> {
+        let protocol = normalise_protocol(&self.protocol);
+        let (from_port, to_port) = parse_ports(&protocol, self.ports.as_ref())?;
+
+        let mut peers = Vec::new();
+        if let Some(cidr) = &self.cidr {
+            peers.push(Peer::Cidr(cidr.clone()));
+        }
+        if let Some(cidr) = &self.cidr_ipv6 {
+            peers.push(Peer::CidrIpv6(cidr.clone()));
+        }
+        if let Some(id) = &self.prefix_list {
+            peers.push(Peer::PrefixList(id.clone()));
+        }
+        if let Some(group) = &self.group {
+            let id = groups.get(group).unwrap_or(group);
+            peers.push(Peer::Group(id.clone()));
+        }
+        if peers.len() != 1 {
+            anyhow::bail!(
+                "a rule needs exactly one of cidr, cidr_ipv6, prefix_list or group, got {}",
+                peers.len()
+            );
+        }
+
+        Ok(Rule {
+            key: RuleKey {
+                protocol,
+                from_port,
+                to_port,
+                peer: peers.remove(0),
+            },
+            description: self.description.clone(),
+        })
+    }
+}
+
+impl TryFrom<&SecurityGroupRule> for LiveRule {
+    type Error = anyhow::Error;
+
+    fn try_from(rule: &SecurityGroupRule) -> Result<Self, Self::Error> {
+        let id = rule
+            .security_group_rule_id()
+            .ok_or_else(|| anyhow::anyhow!("security group rule has no ID"))?;
+
+        let peer = if let Some(cidr) = rule.cidr_ipv4() {
+            Peer::Cidr(cidr.to_string())
+        } else if let Some(cidr) = rule.cidr_ipv6() {
+            Peer::CidrIpv6(cidr.to_string())
+        } else if let Some(id) = rule.prefix_list_id() {
+            Peer::PrefixList(id.to_string())
+        } else if let Some(id) = rule.referenced_group_info().and_then(|g| g.group_id()) {
+            Peer::Group(id.to_string())
+        } else {
+            anyhow::bail!("security group rule {} has no source or destination", id);
+        };
+
+        Ok(LiveRule {
+            id: id.to_string(),
+            group_id: rule.group_id().unwrap_or_default().to_string(),
+            direction: if rule.is_egress().unwrap_or_default() {
+                Direction::Egress
+            } else {
+                Direction::Ingress
+            },
+            rule: Rule {
+                key: RuleKey {
+                    protocol: normalise_protocol(rule.ip_protocol().unwrap_or("-1")),
+                    from_port: rule.from_port().unwrap_or(-1),
+                    to_port: rule.to_port().unwrap_or(-1),
+                    peer,
+                },
+                description: rule.description().map(str::to_string),
+            },
+            peer_owner_id: rule
+                .referenced_group_info()
+                .and_then(|g| g.user_id())
+                .map(str::to_string),
+        })
+    }
+}
+
+impl From<&Rule> for IpPermission {
+    fn from(rule: &Rule) -> Self {
+        let description = rule.description.clone();
+        let builder = IpPermission::builder().ip_protocol(&rule.key.protocol);
+        let builder = if rule.key.protocol == "-1" {
+            builder
+        } else {
+            builder
+                .from_port(rule.key.from_port)
+                .to_port(rule.key.to_port)
+        };
+
+        match &rule.key.peer {
+            Peer::Cidr(cidr) => builder.ip_ranges(
+                IpRange::builder()
+                    .cidr_ip(cidr)
+                    .set_description(description)
+                    .build(),
+            ),
+            Peer::CidrIpv6(cidr) => builder.ipv6_ranges(
+                Ipv6Range::builder()
+                    .cidr_ipv6(cidr)
+                    .set_description(description)
+                    .build(),
+            ),
+            Peer::PrefixList(id) => builder.prefix_list_ids(
+                PrefixListId::builder()
+                    .prefix_list_id(id)
+                    .set_description(description)
+                    .build(),
+            ),
+            Peer::Group(id) => builder.user_id_group_pairs(
+                UserIdGroupPair::builder()
+                    .group_id(id)
+                    .set_description(description)
+                    .build(),
+            ),
+        }
+        .build()
+    }
+}
+
+// Work out the calls needed to turn the live rules for one direction into the
+// desired ones.  Rules are matched on protocol, ports and peer; a rule that only
+// differs by description is updated in place rather than revoked.
+pub fn diff(direction: Direction, desired: &[Rule], live: &[LiveRule]) -> Vec<Change> {
+    let mut live: BTreeMap<&RuleKey, &LiveRule> = live
+        .iter()
+        .filter(|l| l.direction == direction)
+        .map(|l| (&l.rule.key, l))
+        .collect();
+
+    let mut changes = Vec::new();
+    let mut seen = Vec::new();
+    for rule in desired {
+        if seen.contains(&&rule.key) {
+            continue;
+        }
+        seen.push(&rule.key);
+
+        match live.remove(&rule.key) {
+            Some(existing) if existing.rule.description != rule.description => changes.push(
+                Change::Describe(direction, existing.id.clone(), rule.clone()),
+            ),
+            Some(_) => {}
+            None => changes.push(Change::Authorize(direction, rule.clone())),
+        }
+    }
+
+    for existing in live.into_values() {
+        changes.push(Change::Revoke(
+            direction,
+            existing.id.clone(),
+            existing.rule.clone(),
+        ));
+    }
+
+    changes
+}
+
+pub async fn apply_file(client: &Client, path: &Path, dry_run: bool) -> anyhow::Result<()> {
+    let file = RuleFile::from_path(path)?;
+
+    let mut groups = describe_groups(client, &file.vpc_id).await?;
+    for spec in &file.groups {
+        if groups.contains_key(&spec.name) {
+            continue;
+        }
+
+        println!("{}: + create group", spec.name);
+        if dry_run {
+            continue;
+        }
+        let resp = client
+            .create_security_group()
+            .group_name(&spec.name)
+            .description(&spec.description)
+            .vpc_id(&file.vpc_id)
+            .send()
+            .await?;
+        let id = resp
+            .group_id()
+            .expect("should always get a security group ID back");
+        println!("{}: created {}", spec.name, id);
+        groups.insert(spec.name.clone(), id.to_string());
+    }
+
+    for spec in &file.groups {
+        let live = match groups.get(&spec.name) {
+            Some(id) => describe_rules(client, Some(id)).await?,
+            None => Vec::new(),
+        };
+
+        let ingress = spec
+            .ingress
+            .iter()
+            .map(|r| r.resolve(&groups))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut changes = diff(Direction::Ingress, &ingress, &live);
+
+        if let Some(egress) = &spec.egress {
+            let egress = egress
+                .iter()
+                .map(|r| r.resolve(&groups))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            changes.extend(diff(Direction::Egress, &egress, &live));
+        }
+
+        if changes.is_empty() {
+            println!("{}: up to date", spec.name);
+            continue;
+        }
+        for change in &changes {
+            println!("{}: {}", spec.name, change);
+        }
+
+        if let (false, Some(id)) = (dry_run, groups.get(&spec.name)) {
+            apply_changes(client, id, &changes).await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Authorize before revoking so that replacing a rule never leaves a gap.
+pub async fn apply_changes(
+    client: &Client,
+    group_id: &str,
+    changes: &[Change],
+) -> anyhow::Result<()> {
+    for change in changes {
+        match change {
+            Change::Authorize(Direction::Ingress, rule) => {
+                client
+                    .authorize_security_group_ingress()
+                    .group_id(group_id)
+                    .ip_permissions(rule.into())
+                    .send()
+                    .await?;
+            }
+            Change::Authorize(Direction::Egress, rule) => {
+                client
+                    .authorize_security_group_egress()
+                    .group_id(group_id)
+                    .ip_permissions(rule.into())
+                    .send()
+                    .await?;
+            }
+            Change::Describe(direction, id, rule) => {
+                let description = SecurityGroupRuleDescription::builder()
+                    .security_group_rule_id(id)
+                    .description(rule.description.clone().unwrap_or_default())
+                    .build();
+                match direction {
+                    Direction::Ingress => {
+                        client
+                            .update_security_group_rule_descriptions_ingress()
+                            .group_id(group_id)
+                            .security_group_rule_descriptions(description)
+                            .send()
+                            .await?;
+                    }
+                    Direction::Egress => {
+                        client
+                            .update_security_group_rule_descriptions_egress()
+                            .group_id(group_id)
+                            .security_group_rule_descriptions(description)
+                            .send()
+                            .await?;
+                    }
+                }
+            }
+            Change::Revoke(..) => {}
+        }
+    }
+
+    for change in changes {
+        match change {
+            Change::Revoke(Direction::Ingress, id, _) => {
+                client
+                    .revoke_security_group_ingress()
+                    .group_id(group_id)
+                    .security_group_rule_ids(id)
+                    .send()
+                    .await?;
+            }
+            Change::Revoke(Direction::Egress, id, _) => {
+                client
+                    .revoke_security_group_egress()
+                    .group_id(group_id)
+                    .security_group_rule_ids(id)
+                    .send()
+                    .await?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+pub struct Group {
+    pub name: String,
+    pub vpc_id: String,
+    pub owner_id: String,
+    pub is_default: bool,
+}
+
+// Every security group in the region, keyed by group ID.
+pub async fn describe_all_groups(client: &Client) -> anyhow::Result<HashMap<String, Group>> {
+    let mut groups = HashMap::new();
+    let mut next_token = None;
+    loop {
+        let resp = client
+            .describe_security_groups()
+            .set_next_token(next_token)
+            .send()
+            .await?;
+
+        for sg in resp.security_groups().unwrap_or_default() {
+            let id = sg
+                .group_id()
+                .expect("should always get a security group ID");
+            let name = sg.group_name().unwrap_or_default();
+            groups.insert(
+                id.to_string(),
+                Group {
+                    name: name.to_string(),
+                    vpc_id: sg.vpc_id().unwrap_or_default().to_string(),
+                    owner_id: sg.owner_id().unwrap_or_default().to_string(),
+                    is_default: name == "default",
+                },
+            );
+        }
+
+        next_token = resp.next_token().map(str::to_string);
+        if next_token.is_none() {
+            break;
+        }
+    }
+
+    Ok(groups)
+}
+
+// Map of group name to group ID for every security group in the VPC.
+pub async fn describe_groups(
+    client: &Client,
+    vpc_id: &str,
+) -> anyhow::Result<HashMap<String, String>> {
+    let filter = Filter::builder().name("vpc-id").values(vpc_id).build();
+
+    let mut groups = HashMap::new();
+    let mut next_token = None;
+    loop {
+        let resp = client
+            .describe_security_groups()
+            .filters(filter.clone())
+            .set_next_token(next_token)
+            .send()
+            .await?;
+
+        for sg in resp.security_groups().unwrap_or_default() {
+            if let (Some(name), Some(id)) = (sg.group_name(), sg.group_id()) {
+                groups.insert(name.to_string(), id.to_string());
+            }
+        }
+
+        next_token = resp.next_token().map(str::to_string);
+        if next_token.is_none() {
+            break;
+        }
+    }
+
+    Ok(groups)
+}
+
+// Every rule in the region when `group_id` is None.
+pub async fn describe_rules(
+    client: &Client,
+    group_id: Option<&str>,
+) -> anyhow::Result<Vec<LiveRule>> {
+    let filter = group_id.map(|id| Filter::builder().name("group-id").values(id).build());
+
+    let mut rules = Vec::new();
+    let mut next_token = None;
+    loop {
+        let resp = client
+            .describe_security_group_rules()
+            .set_filters(filter.clone().map(|f| vec![f]))
+            .set_next_token(next_token)
+            .send()
+            .await?;
+
+        for rule in resp.security_group_rules().unwrap_or_default() {
+            rules.push(LiveRule::try_from(rule)?);
+        }
+
+        next_token = resp.next_token().map(str::to_string);
+        if next_token.is_none() {
+            break;
+        }
+    }
+
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(ports: i32, cidr: &str, description: Option<&str>) -> Rule {
+        Rule {
+            key: RuleKey {
+                protocol: "tcp".to_string(),
+                from_port: ports,
+                to_port: ports,
+                peer: Peer::Cidr(cidr.to_string()),
+            },
+            description: description.map(str::to_string),
+        }
+    }
+
+    fn live(id: &str, direction: Direction, rule: Rule) -> LiveRule {
+        LiveRule {
+            id: id.to_string(),
+            group_id: "sg-1".to_string(),
+            direction,
+            rule,
+            peer_owner_id: None,
+        }
+    }
+
+    fn summary(changes: &[Change]) -> Vec<String> {
+        changes.iter().map(|c| c.to_string()).collect()
+    }
+
+    fn range(range: &str) -> Option<PortSpec> {
+        Some(PortSpec::Range(range.to_string()))
+    }
+
+    #[test]
+    fn port_ranges() {
+        assert_eq!(parse_ports("tcp", None).unwrap(), (0, 65535));
+        assert_eq!(
+            parse_ports("tcp", Some(&PortSpec::Single(22))).unwrap(),
+            (22, 22)
+        );
+        assert_eq!(
+            parse_ports("udp", range(" 8000 - 8100 ").as_ref()).unwrap(),
+            (8000, 8100)
+        );
+        assert!(parse_ports("tcp", range("443-80").as_ref()).is_err());
+        assert!(parse_ports("-1", Some(&PortSpec::Single(22))).is_err());
+    }
+
+    #[test]
+    fn icmp_ports_are_type_and_code() {
+        // Echo request.
+        assert_eq!(parse_ports("icmp", range("8-0").as_ref()).unwrap(), (8, 0));
+        assert_eq!(
+            parse_ports("icmp", Some(&PortSpec::Single(8))).unwrap(),
+            (8, -1)
+        );
+        assert_eq!(parse_ports("icmp", range("-1").as_ref()).unwrap(), (-1, -1));
+        assert_eq!(
+            parse_ports("58", range("-1--1").as_ref()).unwrap(),
+            (-1, -1)
+        );
+        assert_eq!(parse_ports("icmp", None).unwrap(), (-1, -1));
+        assert!(parse_ports("icmp", range("-1-0").as_ref()).is_err());
+        assert!(parse_ports("icmp", range("256").as_ref()).is_err());
+    }
+
+    #[test]
+    fn diff_adds_missing_rules_and_revokes_extra_ones() {
+        let desired = vec![rule(443, "0.0.0.0/0", None)];
+        let live = vec![live(
+            "sgr-1",
+            Direction::Ingress,
+            rule(22, "0.0.0.0/0", None),
+        )];
+
+        let changes = diff(Direction::Ingress, &desired, &live);
+        assert_eq!(
+            summary(&changes),
+            vec![
+                "+ ingress tcp/443 0.0.0.0/0",
+                "- ingress tcp/22 0.0.0.0/0 (sgr-1)"
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_updates_descriptions_in_place() {
+        let desired = vec![rule(443, "0.0.0.0/0", Some("HTTPS"))];
+        let live = vec![live(
+            "sgr-1",
+            Direction::Ingress,
+            rule(443, "0.0.0.0/0", None),
+        )];
+
+        let changes = diff(Direction::Ingress, &desired, &live);
+        assert_eq!(
+            summary(&changes),
+            vec!["~ ingress tcp/443 0.0.0.0/0 (sgr-1) \"HTTPS\""]
+        );
+    }
+
+    #[test]
+    fn diff_leaves_matching_rules_alone() {
+        let desired = vec![rule(443, "0.0.0.0/0", Some("HTTPS"))];
+        let live = vec![live(
+            "sgr-1",
+            Direction::Ingress,
+            rule(443, "0.0.0.0/0", Some("HTTPS")),
+        )];
+
+        assert!(diff(Direction::Ingress, &desired, &live).is_empty());
+    }
+
+    #[test]
+    fn diff_only_looks_at_one_direction() {
+        let live = vec![live(
+            "sgr-1",
+            Direction::Egress,
+            rule(443, "0.0.0.0/0", None),
+        )];
+
+        assert!(diff(Direction::Ingress, &[], &live).is_empty());
+        assert_eq!(
+            summary(&diff(Direction::Egress, &[], &live)),
+            vec!["- egress tcp/443 0.0.0.0/0 (sgr-1)"]
+        );
+    }
+
+    #[test]
+    fn diff_authorizes_a_duplicated_rule_once() {
+        let desired = vec![
+            rule(443, "0.0.0.0/0", Some("first")),
+            rule(443, "0.0.0.0/0", Some("second")),
+        ];
+
+        let changes = diff(Direction::Ingress, &desired, &[]);
+        assert_eq!(
+            summary(&changes),
+            vec!["+ ingress tcp/443 0.0.0.0/0 \"first\""]
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+// The body of a message S3 sends to a queue.  When a notification is
+// configured S3 sends a test event, which has a different shape.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Notification {
+    Records {
+        #[serde(rename = "Records")]
+        records: Vec<Record>,
+    },
+    Test(TestEvent),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TestEvent {
+    pub event: String,
+    pub bucket: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Record {
+    // For example "ObjectCreated:Put".
+    pub event_name: String,
+    pub event_time: String,
+    pub s3: S3Entity,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct S3Entity {
+    pub bucket: BucketEntity,
+    pub object: ObjectEntity,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BucketEntity {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectEntity {
+    // URL encoded, use Record::key for the actual key.
+    pub key: String,
+    // Not sent for deletes.
+    #[serde(default)]
+    pub size: u64,
+    pub version_id: Option<String>,
+}
+
+impl Notification {
+    pub fn parse(body: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(body)?)
+    }
+}
+
+impl Record {
+    // Keys are form encoded, so a space is a + and a + is %2B.
+    pub fn key(&self) -> String {
+        let key = self.s3.object.key.replace('+', " ");
+        match urlencoding::decode(&key) {
+            Ok(key) => key.into_owned(),
+            Err(_) => key,
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} s3://{}/{} ({} bytes)",
+            self.event_time,
+            self.event_name,
+            self.s3.bucket.name,
+            self.key(),
+            self.s3.object.size
+        )?;
+        if let Some(version_id) = &self.s3.object.version_id {
+            write!(f, " version {}", version_id)?;
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,20 @@
 use clap::{Args, Parser, Subcommand};
 
 use aws_config::meta::region::RegionProviderChain;
+use aws_sdk_sqs::error::SdkError;
 use aws_sdk_sqs::Client;
+use futures::FutureExt;
 
 use aws_arn::ResourceName;
 
+use events::Notification;
+use notify::KeyFilter;
+use worker::{CommandHandler, WorkerOptions};
+
+mod events;
+mod notify;
+mod worker;
+
 #[derive(Parser)]
 struct Cli {
     #[clap(subcommand)]
@@ -18,6 +28,14 @@ enum Command {
     Describe(DescribeArgs),
     List,
     Message(MessageArgs),
+    /// Send a bucket's object created events to a queue, creating the queue
+    /// if it doesn't exist
+    Notify(NotifyArgs),
+    /// Print the S3 events in a queue, deleting them once they're printed
+    Events(EventsArgs),
+    /// Run a command for each message with the body on its stdin, deleting
+    /// the message only if the command succeeds
+    Work(WorkArgs),
 }
 
 #[derive(Args)]
@@ -35,6 +53,41 @@ struct DescribeArgs {
     name: String,
 }
 
+#[derive(Args)]
+struct NotifyArgs {
+    bucket: String,
+    queue: String,
+    /// Only send events for keys starting with this
+    #[clap(long)]
+    prefix: Option<String>,
+    /// Only send events for keys ending with this, e.g. ".png"
+    #[clap(long)]
+    suffix: Option<String>,
+}
+
+#[derive(Args)]
+struct EventsArgs {
+    queue: String,
+    /// Keep waiting for events rather than stopping when the queue is empty
+    #[clap(long)]
+    follow: bool,
+}
+
+#[derive(Args)]
+struct WorkArgs {
+    queue: String,
+    /// Number of messages to work on at once
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    workers: u16,
+    /// Seconds a message is hidden from other consumers, extended while the
+    /// command is still running
+    #[clap(long, default_value_t = 30, value_parser = clap::value_parser!(i32).range(1..=43200))]
+    visibility_timeout: i32,
+    /// The command to run, after --
+    #[clap(last = true, required = true)]
+    command: Vec<String>,
+}
+
 #[derive(Args)]
 struct MessageArgs {
     #[clap(subcommand)]
@@ -46,7 +99,7 @@ struct MessageArgs {
 #[derive(Subcommand)]
 enum MessageCommand {
     Send(SendArgs),
-    Get,
+    Get(GetArgs),
 }
 
 #[derive(Args)]
@@ -54,11 +107,34 @@ struct SendArgs {
     message: String,
 }
 
+#[derive(Args)]
+struct GetArgs {
+    /// Seconds to wait for a message to arrive, up to 20.  Defaults to 0, or
+    /// 20 with --follow
+    #[clap(long, value_parser = clap::value_parser!(i32).range(0..=20))]
+    wait_seconds: Option<i32>,
+    /// Most messages to receive at once, up to 10
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(i32).range(1..=10))]
+    max: i32,
+    /// Seconds received messages are hidden from other consumers, the
+    /// queue's default if not given
+    #[clap(long, value_parser = clap::value_parser!(i32).range(0..=43200))]
+    visibility_timeout: Option<i32>,
+    /// Leave messages on the queue, they can be received again once the
+    /// visibility timeout passes
+    #[clap(long)]
+    no_delete: bool,
+    /// Keep receiving messages until interrupted with Ctrl-C
+    #[clap(long)]
+    follow: bool,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let region_provider = RegionProviderChain::default_provider().or_else("eu-west-1");
     let config = aws_config::from_env().region(region_provider).load().await;
     let client = Client::new(&config);
+    let s3 = aws_sdk_s3::Client::new(&config);
 
     let cli = Cli::parse();
     match cli.command {
@@ -70,8 +146,19 @@ async fn main() -> anyhow::Result<()> {
             MessageCommand::Send(message) => {
                 send_message(&client, &args.queue, &message.message).await?
             }
-            MessageCommand::Get => get_message(&client, &args.queue).await?,
+            MessageCommand::Get(get) => get_messages(&client, &args.queue, &get).await?,
         },
+        Command::Notify(args) => notify(&client, &s3, &args).await?,
+        Command::Events(args) => print_events(&client, &args.queue, args.follow).await?,
+        Command::Work(args) => {
+            let url = name_to_url(&client, &args.queue).await?;
+            let handler = CommandHandler::new(&args.command)?;
+            let options = WorkerOptions {
+                workers: args.workers.into(),
+                visibility_timeout: args.visibility_timeout,
+            };
+            worker::work(&client, &url, handler, &options).await?
+        }
     }
 
     Ok(())
@@ -131,6 +218,20 @@ async fn name_to_url(client: &Client, name: &str) -> anyhow::Result<String> {
     Err(anyhow::anyhow!(format!("Could not find URL for {}", name)))
 }
 
+async fn queue_arn(client: &Client, url: &str) -> anyhow::Result<String> {
+    let resp = client
+        .get_queue_attributes()
+        .queue_url(url)
+        .attribute_names(aws_sdk_sqs::types::QueueAttributeName::QueueArn)
+        .send()
+        .await?;
+
+    resp.attributes()
+        .and_then(|a| a.get(&aws_sdk_sqs::types::QueueAttributeName::QueueArn))
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("Could not get ARN for {}", url))
+}
+
 async fn create_queue(client: &Client, name: &str) -> anyhow::Result<()> {
     let resp = client.create_queue().queue_name(name).send().await?;
 
@@ -192,42 +293,171 @@ async fn send_message(client: &Client, name: &str, message: &str) -> anyhow::Res
     Ok(())
 }
 
-async fn get_message(client: &Client, name: &str) -> anyhow::Result<()> {
+async fn get_messages(client: &Client, name: &str, args: &GetArgs) -> anyhow::Result<()> {
+    let url = name_to_url(client, name).await?;
+    // Long polling when following, rather than asking again and again.
+    let wait_seconds = args
+        .wait_seconds
+        .unwrap_or(if args.follow { 20 } else { 0 });
+
+    // Made once so a Ctrl-C while messages are being printed and deleted
+    // isn't missed, it stops the loop before the next request.
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        let request = client
+            .receive_message()
+            .queue_url(&url)
+            .max_number_of_messages(args.max)
+            .wait_time_seconds(wait_seconds)
+            .set_visibility_timeout(args.visibility_timeout);
+
+        // Only the wait is interrupted, messages that have been received are
+        // still printed and deleted.
+        let resp = tokio::select! {
+            resp = request.send() => resp?,
+            _ = &mut ctrl_c => break,
+        };
+
+        let messages = resp.messages().unwrap_or_default();
+        if messages.is_empty() && !args.follow {
+            println!("No messages in queue '{}'", name);
+        }
+        for message in messages {
+            println!(
+                "Message '{}' '{}'",
+                message
+                    .message_id()
+                    .expect("a message should have a message ID"),
+                message.body().unwrap_or("No message body")
+            );
+            if args.no_delete {
+                continue;
+            }
+            client
+                .delete_message()
+                .queue_url(&url)
+                .receipt_handle(
+                    message
+                        .receipt_handle()
+                        .expect("a message should have a receipt handle"),
+                )
+                .send()
+                .await?;
+        }
+
+        if !args.follow || (&mut ctrl_c).now_or_never().is_some() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+async fn notify(client: &Client, s3: &aws_sdk_s3::Client, args: &NotifyArgs) -> anyhow::Result<()> {
+    // S3 can only send events to a queue in the bucket's region.
+    let bucket_region = aws_common::s3::bucket_region(s3, &args.bucket).await?;
+    let region = client
+        .conf()
+        .region()
+        .map(|r| r.to_string())
+        .unwrap_or_default();
+    if bucket_region != region {
+        anyhow::bail!(
+            "Bucket '{}' is in {} but the queue would be in {}, set AWS_REGION={}",
+            args.bucket,
+            bucket_region,
+            region,
+            bucket_region
+        );
+    }
+
+    let url = match client.get_queue_url().queue_name(&args.queue).send().await {
+        Ok(resp) => resp
+            .queue_url()
+            .expect("queue should have a URL")
+            .to_string(),
+        Err(SdkError::ServiceError(err)) if err.err().is_queue_does_not_exist() => {
+            let resp = client.create_queue().queue_name(&args.queue).send().await?;
+            let url = resp.queue_url().expect("queue should have a URL");
+            println!("Created '{}' with URL '{}'", args.queue, url);
+            url.to_string()
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let arn = queue_arn(client, &url).await?;
+
+    notify::allow_bucket(client, &url, &arn, &args.bucket).await?;
+    let filter = KeyFilter {
+        prefix: args.prefix.clone(),
+        suffix: args.suffix.clone(),
+    };
+    let id = format!("sqs-{}", args.queue);
+    notify::notify_queue(s3, &args.bucket, &id, &arn, &filter).await?;
+
+    println!(
+        "Object created events from '{}' will be sent to '{}'",
+        args.bucket, args.queue
+    );
+
+    Ok(())
+}
+
+async fn print_events(client: &Client, name: &str, follow: bool) -> anyhow::Result<()> {
     let url = name_to_url(client, name).await?;
 
-    let resp = client
-        .receive_message()
-        .queue_url(&url)
-        .max_number_of_messages(1)
-        .send()
-        .await?;
+    // As for get, a Ctrl-C stops the wait or the loop before the next request.
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        let request = client
+            .receive_message()
+            .queue_url(&url)
+            .max_number_of_messages(10)
+            .wait_time_seconds(20);
+        let resp = tokio::select! {
+            resp = request.send() => resp?,
+            _ = &mut ctrl_c => break,
+        };
+
+        let messages = resp.messages().unwrap_or_default();
+        for message in messages {
+            let id = message.message_id().unwrap_or_default();
+            // Anything that isn't an S3 event is left for something else to
+            // deal with, it'll go back on the queue when it becomes visible.
+            match Notification::parse(message.body().unwrap_or_default()) {
+                Ok(Notification::Records { records }) => {
+                    for record in records {
+                        println!("{}", record);
+                    }
+                }
+                Ok(Notification::Test(test)) => {
+                    println!("{} from bucket '{}'", test.event, test.bucket)
+                }
+                Err(err) => {
+                    eprintln!("Message '{}' is not an S3 event: {}", id, err);
+                    continue;
+                }
+            }
 
-    if let Some(messages) = resp.messages() {
-        if messages.len() > 0 {
-            for message in messages {
-                println!(
-                    "Message '{}' '{}'",
+            client
+                .delete_message()
+                .queue_url(&url)
+                .receipt_handle(
                     message
-                        .message_id()
-                        .expect("a message should have a message ID"),
-                    message.body().unwrap_or("No message body")
-                );
-                client
-                    .delete_message()
-                    .queue_url(&url)
-                    .receipt_handle(
-                        message
-                            .receipt_handle()
-                            .expect("a message should have a receipt handle"),
-                    )
-                    .send()
-                    .await?;
-            }
-            return Ok(());
+                        .receipt_handle()
+                        .expect("a message should have a receipt handle"),
+                )
+                .send()
+                .await?;
         }
-    }
 
-    println!("No messages in queue '{}'", name);
+        if (messages.is_empty() && !follow) || (&mut ctrl_c).now_or_never().is_some() {
+            break;
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,175 @@
+use aws_common::Backoff;
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_sdk_s3::types::{
+    Event, FilterRule, FilterRuleName, NotificationConfiguration, NotificationConfigurationFilter,
+    QueueConfiguration, S3KeyFilter,
+};
+use aws_sdk_sqs::types::QueueAttributeName;
+use serde_json::{json, Value};
+
+// All the ways an object can be created: Put, Post, Copy and
+// CompleteMultipartUpload.
+const OBJECT_CREATED: &str = "s3:ObjectCreated:*";
+
+// S3 checks it can send to the queue when the notification is configured,
+// and a queue policy that has just been set can take a little while to apply.
+const VALIDATION_RETRIES: u32 = 5;
+
+pub struct KeyFilter {
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+}
+
+fn bucket_arn(bucket: &str) -> String {
+    format!("arn:aws:s3:::{}", bucket)
+}
+
+// Statement IDs can only contain letters and numbers.
+fn statement_id(bucket: &str) -> String {
+    let name: String = bucket
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect();
+    format!("AllowS3Notifications{}", name)
+}
+
+// Add a statement to the queue's policy letting S3 send messages for the
+// bucket, replacing the statement from an earlier run and leaving any
+// others alone.
+pub async fn allow_bucket(
+    client: &aws_sdk_sqs::Client,
+    queue_url: &str,
+    queue_arn: &str,
+    bucket: &str,
+) -> anyhow::Result<()> {
+    let resp = client
+        .get_queue_attributes()
+        .queue_url(queue_url)
+        .attribute_names(QueueAttributeName::Policy)
+        .send()
+        .await?;
+
+    let mut policy = match resp
+        .attributes()
+        .and_then(|a| a.get(&QueueAttributeName::Policy))
+    {
+        Some(policy) => serde_json::from_str(policy)?,
+        None => json!({ "Version": "2012-10-17" }),
+    };
+
+    let sid = statement_id(bucket);
+    let mut statements = match policy["Statement"].take() {
+        Value::Array(statements) => statements,
+        Value::Null => Vec::new(),
+        statement => vec![statement],
+    };
+    statements.retain(|s| s["Sid"] != sid.as_str());
+    statements.push(json!({
+        "Sid": sid,
+        "Effect": "Allow",
+        "Principal": { "Service": "s3.amazonaws.com" },
+        "Action": "sqs:SendMessage",
+        "Resource": queue_arn,
+        "Condition": { "ArnEquals": { "aws:SourceArn": bucket_arn(bucket) } }
+    }));
+    policy["Statement"] = Value::Array(statements);
+
+    client
+        .set_queue_attributes()
+        .queue_url(queue_url)
+        .attributes(QueueAttributeName::Policy, policy.to_string())
+        .send()
+        .await?;
+
+    Ok(())
+}
+
+// Send object created events for keys matching the filter to the queue.  The
+// bucket's other notifications are kept, a queue notification with the same
+// ID is replaced.
+pub async fn notify_queue(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    id: &str,
+    queue_arn: &str,
+    filter: &KeyFilter,
+) -> anyhow::Result<()> {
+    let current = client
+        .get_bucket_notification_configuration()
+        .bucket(bucket)
+        .send()
+        .await?;
+
+    let mut rules = Vec::new();
+    if let Some(prefix) = &filter.prefix {
+        rules.push(
+            FilterRule::builder()
+                .name(FilterRuleName::Prefix)
+                .value(prefix)
+                .build(),
+        );
+    }
+    if let Some(suffix) = &filter.suffix {
+        rules.push(
+            FilterRule::builder()
+                .name(FilterRuleName::Suffix)
+                .value(suffix)
+                .build(),
+        );
+    }
+    let key_filter = (!rules.is_empty()).then(|| {
+        NotificationConfigurationFilter::builder()
+            .key(S3KeyFilter::builder().set_filter_rules(Some(rules)).build())
+            .build()
+    });
+
+    let mut queues: Vec<QueueConfiguration> = current
+        .queue_configurations()
+        .unwrap_or_default()
+        .iter()
+        .filter(|q| q.id() != Some(id))
+        .cloned()
+        .collect();
+    queues.push(
+        QueueConfiguration::builder()
+            .id(id)
+            .queue_arn(queue_arn)
+            .events(Event::from(OBJECT_CREATED))
+            .set_filter(key_filter)
+            .build(),
+    );
+
+    let config = NotificationConfiguration::builder()
+        .set_topic_configurations(current.topic_configurations().map(|t| t.to_vec()))
+        .set_queue_configurations(Some(queues))
+        .set_lambda_function_configurations(
+            current.lambda_function_configurations().map(|l| l.to_vec()),
+        )
+        .set_event_bridge_configuration(current.event_bridge_configuration().cloned())
+        .build();
+
+    let mut backoff = Backoff::new();
+    let mut attempt = 0;
+    loop {
+        let result = client
+            .put_bucket_notification_configuration()
+            .bucket(bucket)
+            .notification_configuration(config.clone())
+            .send()
+            .await;
+        match result {
+            Ok(_) => return Ok(()),
+            Err(err)
+                if attempt < VALIDATION_RETRIES
+                    && err.code() == Some("InvalidArgument")
+                    && err
+                        .message()
+                        .is_some_and(|m| m.contains("Unable to validate")) =>
+            {
+                attempt += 1;
+                backoff.wait().await;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use aws_common::Backoff;
+use aws_sdk_sqs::error::DisplayErrorContext;
+use aws_sdk_sqs::types::Message;
+use aws_sdk_sqs::Client;
+use futures::future::BoxFuture;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+
+// Something that does the work a message asks for.  A message is only deleted
+// once handle succeeds, so it can be given the same message more than once.
+pub trait Handler: Send + Sync + 'static {
+    fn handle<'a>(&'a self, id: &'a str, body: &'a str) -> BoxFuture<'a, anyhow::Result<()>>;
+}
+
+// Runs a program for each message with the body on its stdin and the message
+// ID in SQS_MESSAGE_ID.  Exiting with status 0 is success.
+pub struct CommandHandler {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandHandler {
+    pub fn new(command: &[String]) -> anyhow::Result<Self> {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("no command to run"))?;
+        Ok(Self {
+            program: program.clone(),
+            args: args.to_vec(),
+        })
+    }
+
+    async fn run(&self, id: &str, body: &str) -> anyhow::Result<()> {
+        let mut child = tokio::process::Command::new(&self.program)
+            .args(&self.args)
+            .env("SQS_MESSAGE_ID", id)
+            .stdin(Stdio::piped())
+            .spawn()?;
+
+        // Dropping stdin closes it, so the command sees the end of the body.
+        let mut stdin = child.stdin.take().expect("stdin should be piped");
+        let written = stdin.write_all(body.as_bytes()).await;
+        drop(stdin);
+
+        let status = child.wait().await?;
+        // A command that exits without reading all of its input is fine.
+        match written {
+            Err(err) if err.kind() != std::io::ErrorKind::BrokenPipe => Err(err.into()),
+            _ if !status.success() => Err(anyhow::anyhow!("'{}' {}", self.program, status)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Handler for CommandHandler {
+    fn handle<'a>(&'a self, id: &'a str, body: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(self.run(id, body))
+    }
+}
+
+pub struct WorkerOptions {
+    pub workers: usize,
+    // Seconds a message is hidden from other workers, extended for as long as
+    // the handler is running.
+    pub visibility_timeout: i32,
+}
+
+// Receive and handle messages with several workers until SIGTERM or Ctrl-C.
+// Workers finish the message they're handling before stopping.
+pub async fn work<H: Handler>(
+    client: &Client,
+    url: &str,
+    handler: H,
+    options: &WorkerOptions,
+) -> anyhow::Result<()> {
+    let handler = Arc::new(handler);
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let mut workers = Vec::new();
+    for worker in 1..=options.workers {
+        let client = client.clone();
+        let url = url.to_string();
+        let handler = handler.clone();
+        let shutdown = shutdown_rx.clone();
+        let visibility_timeout = options.visibility_timeout;
+        workers.push(tokio::spawn(async move {
+            run_worker(
+                worker,
+                &client,
+                &url,
+                handler.as_ref(),
+                visibility_timeout,
+                shutdown,
+            )
+            .await
+        }));
+    }
+    println!("Started {} workers", options.workers);
+
+    shutdown_signal().await?;
+    println!("Shutting down, waiting for running jobs to finish");
+    shutdown_tx.send_replace(true);
+
+    for worker in workers {
+        worker.await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        _ = terminate.recv() => {}
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() -> anyhow::Result<()> {
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+async fn run_worker<H: Handler>(
+    worker: usize,
+    client: &Client,
+    url: &str,
+    handler: &H,
+    visibility_timeout: i32,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    // Receiving can fail when the network or SQS has a problem, workers back
+    // off rather than give up.
+    let mut backoff = Backoff::new();
+    while !*shutdown.borrow() {
+        let request = client
+            .receive_message()
+            .queue_url(url)
+            .max_number_of_messages(1)
+            .wait_time_seconds(20)
+            .visibility_timeout(visibility_timeout);
+
+        let resp = tokio::select! {
+            resp = request.send() => resp,
+            _ = shutdown.changed() => break,
+        };
+        let resp = match resp {
+            Ok(resp) => resp,
+            Err(err) => {
+                eprintln!(
+                    "Worker {}: could not receive messages: {}",
+                    worker,
+                    DisplayErrorContext(&err)
+                );
+                tokio::select! {
+                    _ = backoff.wait() => {}
+                    _ = shutdown.changed() => break,
+                }
+                continue;
+            }
+        };
+        backoff.reset();
+
+        for message in resp.messages().unwrap_or_default() {
+            handle_message(worker, client, url, handler, visibility_timeout, message).await;
+        }
+    }
+}
+
+async fn handle_message<H: Handler>(
+    worker: usize,
+    client: &Client,
+    url: &str,
+    handler: &H,
+    visibility_timeout: i32,
+    message: &Message,
+) {
+    let id = message
+        .message_id()
+        .expect("a message should have a message ID");
+    let receipt_handle = message
+        .receipt_handle()
+        .expect("a message should have a receipt handle");
+
+    // Keep the message hidden while the job runs by pushing the timeout back
+    // at half of it, so a slow job isn't handed to another worker.
+    let extend_every = Duration::from_secs((visibility_timeout as u64 / 2).max(1));
+    let mut extend =
+        tokio::time::interval_at(tokio::time::Instant::now() + extend_every, extend_every);
+
+    let job = handler.handle(id, message.body().unwrap_or_default());
+    tokio::pin!(job);
+    let result = loop {
+        tokio::select! {
+            result = &mut job => break result,
+            _ = extend.tick() => {
+                let resp = client
+                    .change_message_visibility()
+                    .queue_url(url)
+                    .receipt_handle(receipt_handle)
+                    .visibility_timeout(visibility_timeout)
+                    .send()
+                    .await;
+                if let Err(err) = resp {
+                    eprintln!(
+                        "Worker {}: could not extend the visibility of '{}': {}",
+                        worker,
+                        id,
+                        DisplayErrorContext(&err)
+                    );
+                }
+            }
+        }
+    };
+
+    // Either way a message that isn't deleted becomes visible again when the
+    // timeout runs out, and is retried until the queue's redrive policy moves
+    // it to a dead letter queue.
+    if let Err(err) = result {
+        eprintln!("Worker {}: message '{}' failed: {:#}", worker, id, err);
+        return;
+    }
+    let resp = client
+        .delete_message()
+        .queue_url(url)
+        .receipt_handle(receipt_handle)
+        .send()
+        .await;
+    match resp {
+        Ok(_) => println!("Worker {}: handled message '{}'", worker, id),
+        Err(err) => eprintln!(
+            "Worker {}: could not delete message '{}': {}",
+            worker,
+            id,
+            DisplayErrorContext(&err)
+        ),
+    }
+}